@@ -0,0 +1,37 @@
+//! Records the git commit and Rust version for `--build-info`.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let git_sha = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+
+    println!(
+        "cargo:rustc-env=ELGATO_AUTOLIGHT_GIT_SHA={}",
+        git_sha.as_deref().unwrap_or("unknown")
+    );
+    println!(
+        "cargo:rustc-env=ELGATO_AUTOLIGHT_RUSTC_VERSION={}",
+        rustc_version.as_deref().unwrap_or("unknown")
+    );
+
+    // Rebuild when the checked out commit changes. A missing path would make
+    // every build rerun this script, so only existing ones are watched.
+    for path in [".git/HEAD", ".git/refs/heads", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+}
+
+/// The trimmed stdout of a successful command, or `None`.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|s| !s.is_empty())
+}
@@ -0,0 +1,54 @@
+//! Watches the config file so a running monitor picks up edits as soon as
+//! they are saved, without a SIGHUP.
+
+use anyhow::{Context, Result};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+
+/// A watch on the config file, which stops when dropped.
+pub struct ConfigWatch {
+    _watcher: RecommendedWatcher,
+    changes: Receiver<()>,
+}
+
+impl ConfigWatch {
+    /// Whether the file has changed since the last call.
+    pub fn changed(&self) -> bool {
+        self.changes.try_iter().count() > 0
+    }
+}
+
+/// Starts watching the config file at `path`.
+pub fn watch(path: &Path) -> Result<ConfigWatch> {
+    // Editors often save by writing a new file and renaming it over the old
+    // one, which ends a watch on the file itself, so watch the directory and
+    // pick out events for the file by name.
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path.file_name().map(|name| name.to_os_string());
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if event.kind.is_access() {
+            return;
+        }
+        if event.paths.iter().any(|p| p.file_name() == name.as_deref()) {
+            let _ = tx.send(());
+        }
+    })
+    .context("Failed to start the config file watcher")?;
+
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    Ok(ConfigWatch {
+        _watcher: watcher,
+        changes: rx,
+    })
+}
@@ -0,0 +1,27 @@
+//! Desktop notifications posted through `osascript`.
+
+use std::process::Command;
+
+/// Posts a notification saying `lights` were turned to `action`. Failures are
+/// logged and otherwise ignored so they never affect the monitor.
+pub fn lights_switched(lights: &[String], action: &str) {
+    let message = format!("{} turned {action}", lights.join(", "));
+    let script = format!(
+        "display notification \"{}\" with title \"elgato-autolight\"",
+        escape(&message)
+    );
+
+    match Command::new("osascript").args(["-e", &script]).output() {
+        Ok(output) if !output.status.success() => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::warn!("Failed to post notification: {}", stderr.trim());
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Failed to run osascript: {e}"),
+    }
+}
+
+/// Escapes a value for use inside an AppleScript string literal.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
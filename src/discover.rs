@@ -0,0 +1,160 @@
+//! Finding Elgato lights on the local network.
+//!
+//! Elgato lights advertise themselves over Bonjour as `_elg._tcp`. macOS
+//! ships `dns-sd`, so discovery shells out to it rather than speaking mDNS
+//! directly: browse for instances, resolve each one to a host and TXT
+//! record (which carries the model), then look up the host's IPv4 address.
+//! `dns-sd` queries never exit on their own, so each one is read for a
+//! limited time and then killed.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::net::Ipv4Addr;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::{spawn_line_reader, ChildGuard};
+
+const SERVICE_TYPE: &str = "_elg._tcp";
+
+/// How long to wait for a single light to resolve or report its address.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct DiscoveredLight {
+    /// The Bonjour instance name, which is the name shown in Control Center.
+    pub name: String,
+    pub ip_address: Option<String>,
+    pub port: Option<u16>,
+    pub model: Option<String>,
+}
+
+/// Browses for lights for `timeout`, then resolves every one found.
+pub fn discover(timeout: Duration) -> Result<Vec<DiscoveredLight>> {
+    let mut names: Vec<String> = Vec::new();
+    for line in query(&["-B", SERVICE_TYPE, "local."], timeout, |_| false)? {
+        if let Some(name) = parse_browse_line(&line) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names.into_iter().map(resolve).collect()
+}
+
+fn resolve(name: String) -> Result<DiscoveredLight> {
+    let output = query(&["-L", &name, SERVICE_TYPE, "local."], RESOLVE_TIMEOUT, |lines| {
+        // The TXT record is printed on the line after the host.
+        lines
+            .iter()
+            .position(|l| l.contains(" can be reached at "))
+            .is_some_and(|i| i + 1 < lines.len())
+    })?;
+
+    let target = output.iter().find_map(|line| {
+        let rest = line.split_once(" can be reached at ")?.1;
+        let (host, port) = rest.split_whitespace().next()?.rsplit_once(':')?;
+        Some((host.to_string(), port.parse::<u16>().ok()))
+    });
+    let model = output.iter().find_map(|line| txt_value(line, "md"));
+
+    let Some((host, port)) = target else {
+        return Ok(DiscoveredLight {
+            name,
+            ip_address: None,
+            port: None,
+            model,
+        });
+    };
+
+    let output = query(&["-G", "v4", &host], RESOLVE_TIMEOUT, |lines| {
+        lines.iter().any(|l| parse_address_line(l).is_some())
+    })?;
+    let ip_address = output.iter().find_map(|l| parse_address_line(l)).map(|ip| ip.to_string());
+
+    Ok(DiscoveredLight {
+        name,
+        ip_address,
+        port,
+        model,
+    })
+}
+
+/// Runs `dns-sd` and collects its output until `done` returns true or
+/// `timeout` passes.
+fn query(
+    args: &[&str],
+    timeout: Duration,
+    done: impl Fn(&[String]) -> bool,
+) -> Result<Vec<String>> {
+    let mut child = Command::new("dns-sd")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(ChildGuard)
+        .context("Failed to run dns-sd")?;
+    let lines = spawn_line_reader(child.stdout.take().expect("stdout was piped"));
+
+    let deadline = Instant::now() + timeout;
+    let mut output = Vec::new();
+    while !done(&output) {
+        match lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(line) => output.push(line),
+            Err(_) => break,
+        }
+    }
+    Ok(output)
+}
+
+/// Extracts the instance name from a `dns-sd -B` result such as
+/// ` 9:41:00.000  Add  3  6 local.  _elg._tcp.  Elgato Key Light 1A2B`.
+fn parse_browse_line(line: &str) -> Option<String> {
+    if line.split_whitespace().nth(1) != Some("Add") {
+        return None;
+    }
+    let name = line.split_once(&format!("{SERVICE_TYPE}."))?.1.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Extracts the address from a `dns-sd -G` result such as
+/// ` 9:41:00.000  Add  40000002  6  elgato.local.  192.168.1.20  120`.
+fn parse_address_line(line: &str) -> Option<Ipv4Addr> {
+    let mut fields = line.split_whitespace();
+    if fields.nth(1) != Some("Add") {
+        return None;
+    }
+    fields.find_map(|field| field.parse().ok())
+}
+
+/// Reads `key=value` from a TXT record line, where spaces in the value are
+/// escaped as `\ ` or `\032`.
+fn txt_value(line: &str, key: &str) -> Option<String> {
+    let prefix = format!("{key}=");
+    let start = line
+        .match_indices(&prefix)
+        .find(|&(i, _)| i == 0 || line[..i].ends_with(char::is_whitespace))?
+        .0;
+
+    let mut value = String::new();
+    let mut chars = line[start + prefix.len()..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let rest = chars.as_str();
+                let code = rest.get(..3).filter(|d| d.bytes().all(|b| b.is_ascii_digit()));
+                match code.and_then(|d| d.parse::<u8>().ok()) {
+                    Some(byte) => {
+                        value.push(char::from(byte));
+                        chars = rest[3..].chars();
+                    }
+                    None => value.extend(chars.next()),
+                }
+            }
+            c if c.is_whitespace() => break,
+            c => value.push(c),
+        }
+    }
+    (!value.is_empty()).then_some(value)
+}
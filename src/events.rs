@@ -0,0 +1,129 @@
+//! Publishes light transitions to local clients over a Unix domain socket.
+//!
+//! Each transition is written to every connected client as one line of JSON:
+//!
+//! ```text
+//! {"event":"on","timestamp":1718000000}
+//! ```
+//!
+//! `timestamp` is seconds since the Unix epoch. Clients only read; anything
+//! they send is ignored.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::io::Write;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::CameraState;
+
+#[derive(Serialize)]
+struct Event {
+    event: CameraState,
+    timestamp: u64,
+}
+
+pub struct EventSocket {
+    path: PathBuf,
+    clients: Arc<Mutex<Vec<UnixStream>>>,
+}
+
+impl EventSocket {
+    /// Binds the socket at `path`, replacing a stale socket left behind by a
+    /// previous run, and starts accepting clients in the background. Anything
+    /// else already at `path` is left alone and fails the bind.
+    pub fn bind(path: &Path) -> Result<Self> {
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?,
+            Ok(_) => bail!("event_socket {} exists and is not a socket", path.display()),
+            Err(_) => {}
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind event socket {}", path.display()))?;
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = clients.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        // A client that stops reading must never stall the monitor
+                        if stream.set_nonblocking(true).is_ok() {
+                            accepted.lock().unwrap().push(stream);
+                        }
+                    }
+                    Err(e) => log::warn!("Event socket accept failed: {e}"),
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            clients,
+        })
+    }
+
+    /// Sends `state` to every client, dropping clients that have gone away.
+    pub fn publish(&self, state: CameraState) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let Ok(mut line) = serde_json::to_string(&Event {
+            event: state,
+            timestamp,
+        }) else {
+            return;
+        };
+        line.push('\n');
+
+        self.clients
+            .lock()
+            .unwrap()
+            .retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+impl Drop for EventSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("elgato-autolight-{}-{name}.sock", std::process::id()))
+    }
+
+    #[test]
+    fn bind_replaces_a_stale_socket() {
+        let path = socket_path("stale");
+        let _ = std::fs::remove_file(&path);
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let socket = EventSocket::bind(&path).unwrap();
+        assert!(UnixStream::connect(&path).is_ok());
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn bind_leaves_other_files_alone() {
+        let path = socket_path("file");
+        std::fs::write(&path, "notes").unwrap();
+
+        let error = EventSocket::bind(&path).err().unwrap();
+        let contents = std::fs::read_to_string(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(error.to_string().contains(&path.display().to_string()), "{error}");
+        assert_eq!(contents.unwrap(), "notes");
+    }
+}
@@ -0,0 +1,175 @@
+//! A record of camera transitions, appended by the monitor and summarised by
+//! the `history` command as on-time per day and per app.
+
+use anyhow::{Context, Result};
+use chrono::{Days, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::CameraState;
+
+/// Once the file grows past this, the oldest entries are dropped until it is
+/// half this size.
+const MAX_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Record {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub state: CameraState,
+    /// The process that logged the event, when it can be determined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+}
+
+/// Camera on-time for one local calendar day.
+#[derive(Debug, Default)]
+pub struct Day {
+    /// Time at least one app had the camera on.
+    pub total: Duration,
+    pub apps: BTreeMap<String, Duration>,
+}
+
+pub fn path() -> Option<PathBuf> {
+    crate::home_dir().map(|home| home.join(".config/elgato-autolight/history.jsonl"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Appends a transition, pruning old entries when the file gets too big.
+pub fn append(state: CameraState, app: Option<&str>) -> Result<()> {
+    let path = path().context("HOME not set, cannot record history")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    let record = Record {
+        timestamp: now(),
+        state,
+        app: app.map(str::to_string),
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)
+        .with_context(|| format!("Failed to write to {}", path.display()))?;
+
+    if file.metadata().map_or(0, |m| m.len()) > MAX_BYTES {
+        prune(&path).with_context(|| format!("Failed to prune {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Keeps the newest lines that fit in half of `MAX_BYTES`.
+fn prune(path: &Path) -> io::Result<()> {
+    let contents = std::fs::read_to_string(path)?;
+    let mut kept = 0;
+    let mut start = contents.len();
+    for line in contents.lines().rev() {
+        kept += line.len() as u64 + 1;
+        if kept > MAX_BYTES / 2 {
+            break;
+        }
+        start = start.saturating_sub(line.len() + 1);
+    }
+    std::fs::write(path, &contents[start..])
+}
+
+/// Reads every record. A missing file means no history; malformed lines are
+/// skipped.
+pub fn load() -> Result<Vec<Record>> {
+    let path = path().context("HOME not set, cannot read history")?;
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Totals on-time per local day. An app is on from its first `on` to the next
+/// `off` for the same app; the camera as a whole is on while any app is.
+/// Intervals still open at the end of the history run until now, and
+/// intervals spanning midnight are split between the days.
+pub fn summarize(records: &[Record]) -> BTreeMap<NaiveDate, Day> {
+    let mut days = BTreeMap::new();
+    let mut apps_on: HashMap<Option<&str>, u64> = HashMap::new();
+    let mut camera_on: Option<u64> = None;
+
+    for record in records {
+        let app = record.app.as_deref();
+        match record.state {
+            CameraState::On => {
+                apps_on.entry(app).or_insert(record.timestamp);
+                camera_on.get_or_insert(record.timestamp);
+            }
+            CameraState::Off => {
+                if let Some(since) = apps_on.remove(&app) {
+                    add_app(&mut days, app, since, record.timestamp);
+                }
+                if apps_on.is_empty() {
+                    if let Some(since) = camera_on.take() {
+                        add_total(&mut days, since, record.timestamp);
+                    }
+                }
+            }
+        }
+    }
+
+    let now = now();
+    for (app, since) in apps_on {
+        add_app(&mut days, app, since, now);
+    }
+    if let Some(since) = camera_on {
+        add_total(&mut days, since, now);
+    }
+    days
+}
+
+fn add_total(days: &mut BTreeMap<NaiveDate, Day>, start: u64, end: u64) {
+    for (day, span) in split_by_day(start, end) {
+        days.entry(day).or_default().total += span;
+    }
+}
+
+fn add_app(days: &mut BTreeMap<NaiveDate, Day>, app: Option<&str>, start: u64, end: u64) {
+    let Some(app) = app else {
+        return;
+    };
+    for (day, span) in split_by_day(start, end) {
+        *days.entry(day).or_default().apps.entry(app.to_string()).or_default() += span;
+    }
+}
+
+/// Breaks `start..end` into pieces that each fall within one local day.
+fn split_by_day(mut start: u64, end: u64) -> Vec<(NaiveDate, Duration)> {
+    let mut pieces = Vec::new();
+    while start < end {
+        let Some(local) = Local.timestamp_opt(start as i64, 0).single() else {
+            break;
+        };
+        let day = local.date_naive();
+        let next_midnight = day
+            .checked_add_days(Days::new(1))
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .and_then(|d| d.and_local_timezone(Local).earliest())
+            .map_or(end, |d| (d.timestamp() as u64).clamp(start + 1, end));
+
+        pieces.push((day, Duration::from_secs(next_midnight - start)));
+        start = next_midnight;
+    }
+    pieces
+}
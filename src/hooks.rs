@@ -0,0 +1,114 @@
+//! Runs the user's `on_camera_on` and `on_camera_off` commands on a
+//! background thread, one at a time and in order, so a slow or hung hook
+//! never holds up the monitor.
+
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::CameraState;
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// One run of a hook command.
+pub struct Hook {
+    pub command: String,
+    pub state: CameraState,
+    /// Whether any light was switched for this transition.
+    pub switched: bool,
+    /// `None` lets the hook run as long as it takes.
+    pub timeout: Option<Duration>,
+}
+
+/// Runs queued hooks in order. Dropping it waits for the queue to finish,
+/// so hooks for the last transitions still run when the monitor exits.
+pub struct HookRunner {
+    hooks: Option<Sender<Hook>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl HookRunner {
+    pub fn start() -> Self {
+        let (hooks, queue) = mpsc::channel::<Hook>();
+        let worker = std::thread::spawn(move || {
+            for hook in queue {
+                run(&hook);
+            }
+        });
+        Self {
+            hooks: Some(hooks),
+            worker: Some(worker),
+        }
+    }
+
+    /// Queues `hook` to run after any hooks still pending.
+    pub fn run(&self, hook: Hook) {
+        if let Some(ref hooks) = self.hooks {
+            let _ = hooks.send(hook);
+        }
+    }
+}
+
+impl Drop for HookRunner {
+    fn drop(&mut self) {
+        drop(self.hooks.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Runs the command with `/bin/sh`, passing the state as its argument and in
+/// the environment. Failures are only logged.
+fn run(hook: &Hook) {
+    let state = hook.state.action();
+    log::debug!("Running camera {state} hook: {}", hook.command);
+
+    // `"$@"` hands the state to the command as its last argument.
+    let spawned = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", hook.command))
+        .args(["elgato-autolight", state])
+        .env("ELGATO_AUTOLIGHT_STATE", state)
+        .env("ELGATO_AUTOLIGHT_SWITCHED", if hook.switched { "1" } else { "0" })
+        .stdin(Stdio::null())
+        // Its own process group, so a timeout can kill everything it started.
+        .process_group(0)
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn!("Failed to run camera {state} hook: {e}");
+            return;
+        }
+    };
+
+    let deadline = hook.timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return,
+            Ok(Some(status)) => {
+                log::warn!("Camera {state} hook failed ({status}): {}", hook.command);
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::warn!("Failed to wait for camera {state} hook: {e}");
+                return;
+            }
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            let _ = Command::new("kill")
+                .args(["-KILL", "--", &format!("-{}", child.id())])
+                .status();
+            let _ = child.kill();
+            let _ = child.wait();
+            log::warn!("Camera {state} hook timed out and was killed: {}", hook.command);
+            return;
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,58 @@
+//! A PID file held by the running monitor, so a second monitor started
+//! alongside it can tell and keep the two from both switching the light.
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Removes the PID file when dropped, if it still names this process.
+pub struct InstanceLock {
+    path: PathBuf,
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if read_pid().is_some_and(|pid| pid == std::process::id()) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    crate::home_dir().map(|home| home.join(".config/elgato-autolight/monitor.pid"))
+}
+
+fn read_pid() -> Option<u32> {
+    std::fs::read_to_string(path()?).ok()?.trim().parse().ok()
+}
+
+/// The PID of another monitor holding the PID file. A file left behind by a
+/// monitor that was killed, or naming a process that isn't a monitor, is
+/// ignored.
+pub fn other_monitor() -> Option<u32> {
+    read_pid().filter(|&pid| pid != std::process::id() && is_monitor(pid))
+}
+
+/// Whether `pid` is a running elgato-autolight process.
+fn is_monitor(pid: u32) -> bool {
+    Command::new("ps")
+        .args(["-p", &pid.to_string(), "-o", "command="])
+        .output()
+        .is_ok_and(|output| {
+            output.status.success()
+                && String::from_utf8_lossy(&output.stdout).contains("elgato-autolight")
+        })
+}
+
+/// Writes this process's PID to the file, replacing whatever was there.
+pub fn acquire() -> Result<InstanceLock> {
+    let path = path().context("HOME not set, cannot write the PID file")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    std::fs::write(&path, format!("{}\n", std::process::id()))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(InstanceLock { path })
+}
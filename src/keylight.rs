@@ -0,0 +1,198 @@
+//! Controls a light through the HTTP API Elgato lights serve on port 9123,
+//! for lights with `direct_http` set, instead of running `elgato-light`.
+//!
+//! Only the two requests needed are made: `GET /elgato/lights` for the power
+//! state and `PUT /elgato/lights` to change it. Each opens its own
+//! connection and closes it, which is what the lights expect.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::light::{Light, LightState};
+
+const PORT: u16 = 9123;
+const PATH: &str = "/elgato/lights";
+
+#[derive(Deserialize)]
+struct Lights {
+    lights: Vec<Status>,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    on: u8,
+}
+
+/// The JSON body that switches `light` to `state`, on at its temperature or
+/// off.
+pub fn request_body(light: &Light, state: LightState) -> String {
+    let state = match state.brightness(light) {
+        Some(brightness) => serde_json::json!({
+            "on": 1,
+            "brightness": brightness.get(),
+            "temperature": light.temperature.mireds(),
+        }),
+        None => serde_json::json!({ "on": 0 }),
+    };
+    serde_json::json!({ "numberOfLights": 1, "lights": [state] }).to_string()
+}
+
+/// The request switching `light` would make, formatted for display.
+pub fn describe(ip: &str, light: &Light, state: LightState) -> String {
+    format!("PUT http://{ip}:{PORT}{PATH} {}", request_body(light, state))
+}
+
+/// Switches the light at `ip` to `state` at `light`'s settings.
+pub fn set(ip: &str, light: &Light, state: LightState, timeout: Option<Duration>) -> Result<()> {
+    request(ip, "PUT", Some(&request_body(light, state)), timeout).map(|_| ())
+}
+
+/// Whether the light at `ip` is on right now.
+pub fn is_on(ip: &str, timeout: Option<Duration>) -> Result<bool> {
+    let body = request(ip, "GET", None, timeout)?;
+    let lights: Lights = serde_json::from_str(&body)
+        .with_context(|| format!("Unexpected reply from {ip}: {}", body.trim()))?;
+    match lights.lights.first() {
+        Some(state) => Ok(state.on != 0),
+        None => bail!("{ip} reported no lights"),
+    }
+}
+
+/// Makes one request and returns the response body. `timeout` applies to
+/// connecting and to each read and write; `None` waits as long as it takes.
+fn request(
+    ip: &str,
+    method: &str,
+    body: Option<&str>,
+    timeout: Option<Duration>,
+) -> Result<String> {
+    let address = resolve(ip)?;
+    let mut stream = match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+        None => TcpStream::connect(address),
+    }
+    .with_context(|| format!("Failed to connect to {address}"))?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let body = body.unwrap_or("");
+    let request = format!(
+        "{method} {PATH} HTTP/1.1\r\nHost: {ip}:{PORT}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream
+        .write_all(request.as_bytes())
+        .with_context(|| format!("Failed to send request to {address}"))?;
+
+    let (status, body) = read_response(BufReader::new(stream))
+        .with_context(|| format!("Failed to read reply from {address}"))?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(body),
+        Some(_) => bail!("{PATH} on {ip} failed: {status}"),
+        None => bail!("Unexpected reply from {ip}"),
+    }
+}
+
+/// Reads an HTTP response's status line and body. The body is read up to its
+/// `Content-Length`, so a light that keeps the connection open despite
+/// `Connection: close` doesn't hold the reply up until the timeout. Without
+/// one, the body runs until the connection closes.
+fn read_response(mut reader: impl BufRead) -> Result<(String, String)> {
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse::<usize>().context("Invalid Content-Length")?);
+            }
+        }
+    }
+
+    let mut body = Vec::new();
+    match length {
+        Some(length) => {
+            body.resize(length, 0);
+            reader.read_exact(&mut body)?;
+        }
+        None => {
+            reader.read_to_end(&mut body)?;
+        }
+    }
+    Ok((status.trim_end().to_string(), String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn resolve(ip: &str) -> Result<SocketAddr> {
+    (ip, PORT)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {ip}"))?
+        .next()
+        .with_context(|| format!("No address for {ip}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::{Brightness, Temperature};
+
+    fn light() -> Light {
+        Light {
+            name: None,
+            ip_address: Some("192.168.1.20".to_string()),
+            brightness: Brightness::try_from(40).unwrap(),
+            temperature: Temperature::try_from(5000).unwrap(),
+            prefer_discovery: false,
+            direct_http: true,
+        }
+    }
+
+    fn body(state: LightState) -> serde_json::Value {
+        serde_json::from_str(&request_body(&light(), state)).unwrap()
+    }
+
+    #[test]
+    fn on_body_sets_brightness_and_mireds() {
+        assert_eq!(
+            body(LightState::On),
+            serde_json::json!({
+                "numberOfLights": 1,
+                "lights": [{ "on": 1, "brightness": 40, "temperature": 200 }],
+            })
+        );
+        let standby = LightState::Standby(Brightness::try_from(5).unwrap());
+        assert_eq!(body(standby)["lights"][0]["brightness"], 5);
+    }
+
+    #[test]
+    fn off_body_only_turns_off() {
+        assert_eq!(
+            body(LightState::Off),
+            serde_json::json!({ "numberOfLights": 1, "lights": [{ "on": 0 }] })
+        );
+    }
+
+    #[test]
+    fn response_body_stops_at_content_length() {
+        let reply = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     content-length: 13\r\n\r\n{\"lights\":[]}trailing";
+        let (status, body) = read_response(reply.as_bytes()).unwrap();
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, "{\"lights\":[]}");
+    }
+
+    #[test]
+    fn response_body_without_length_runs_to_the_end() {
+        let reply = "HTTP/1.1 200 OK\r\n\r\n{\"lights\":[]}";
+        let (_, body) = read_response(reply.as_bytes()).unwrap();
+        assert_eq!(body, "{\"lights\":[]}");
+    }
+}
@@ -0,0 +1,746 @@
+//! Controlling lights through the `elgato-light` CLI.
+//!
+//! `elgato-light` is only distributed as a binary, so each command spawns it
+//! as a subprocess. The binary is located once at startup and reused for
+//! every event. Everything that talks to a light goes through this module so
+//! another backend can slot in without touching the monitor; lights with
+//! `direct_http` set go to `keylight` instead.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::keylight;
+use crate::units::{Brightness, Temperature};
+use crate::Config;
+
+/// A light with all settings resolved, ready to be passed to `elgato-light`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Light {
+    pub name: Option<String>,
+    pub ip_address: Option<String>,
+    pub brightness: Brightness,
+    pub temperature: Temperature,
+    /// Address the light by name alone when it also has an IP address, and
+    /// only fall back to the IP address if that fails.
+    pub prefer_discovery: bool,
+    /// Control the light over its HTTP API rather than with `elgato-light`,
+    /// when it has an IP address.
+    pub direct_http: bool,
+}
+
+/// What switching a light leaves it at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightState {
+    Off,
+    /// On, dimmed to this brightness instead of the light's own.
+    Standby(Brightness),
+    On,
+}
+
+impl LightState {
+    /// The `elgato-light` subcommand that sets this state.
+    fn action(self) -> &'static str {
+        match self {
+            LightState::Off => "off",
+            LightState::Standby(_) | LightState::On => "on",
+        }
+    }
+
+    /// The brightness `light` is set to, or `None` when it's turned off.
+    pub fn brightness(self, light: &Light) -> Option<Brightness> {
+        match self {
+            LightState::Off => None,
+            LightState::Standby(brightness) => Some(brightness),
+            LightState::On => Some(light.brightness),
+        }
+    }
+}
+
+/// Which of a light's identifiers are passed to `elgato-light`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Selector {
+    /// Every identifier that is set.
+    All,
+    Name,
+    IpAddress,
+}
+
+impl Light {
+    pub fn label(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.ip_address.as_deref())
+            .unwrap_or("default light")
+    }
+
+    /// Whether switching this light runs `elgato-light`.
+    pub fn uses_elgato_light(&self) -> bool {
+        self.http_address().is_none()
+    }
+
+    /// The IP address to reach the light's HTTP API at, when it's controlled
+    /// that way.
+    fn http_address(&self) -> Option<&str> {
+        self.ip_address.as_deref().filter(|_| self.direct_http)
+    }
+
+    /// Selectors to try in order until one works.
+    fn selectors(&self) -> &'static [Selector] {
+        if self.http_address().is_some() {
+            &[Selector::All]
+        } else if self.prefer_discovery && self.name.is_some() && self.ip_address.is_some() {
+            &[Selector::Name, Selector::IpAddress]
+        } else {
+            &[Selector::All]
+        }
+    }
+}
+
+/// Locates `elgato-light`. `configured` (the `elgato_light_path` setting)
+/// replaces the search, so a configured path that can't be run is an error
+/// rather than a reason to run some other binary. `Ok(None)` means the
+/// search found nothing.
+pub fn find_elgato_light(configured: Option<&Path>) -> Result<Option<PathBuf>, String> {
+    find_elgato_light_in(configured, std::env::var_os("PATH").as_deref())
+}
+
+/// Like `find_elgato_light`, but searches `search_path` instead of `PATH`.
+fn find_elgato_light_in(
+    configured: Option<&Path>,
+    search_path: Option<&OsStr>,
+) -> Result<Option<PathBuf>, String> {
+    if let Some(path) = configured {
+        return configured_binary(path).map(Some);
+    }
+    Ok(search(search_path))
+}
+
+fn search(search_path: Option<&OsStr>) -> Option<PathBuf> {
+    // Try PATH first
+    let on_path = search_path
+        .into_iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join("elgato-light"))
+        .find(|path| is_executable(path));
+    if on_path.is_some() {
+        return on_path;
+    }
+
+    // Fallback locations
+    for path in ["/opt/homebrew/bin/elgato-light", "/usr/local/bin/elgato-light"] {
+        let p = PathBuf::from(path);
+        if p.exists() {
+            return Some(p);
+        }
+    }
+
+    None
+}
+
+/// The `elgato_light_path` setting with a leading `~/` expanded, or why it
+/// can't be run.
+pub fn configured_binary(path: &Path) -> Result<PathBuf, String> {
+    // Only a `~` path reads HOME.
+    let expanded = path.strip_prefix("~").ok().and_then(|rest| Some(crate::home_dir()?.join(rest)));
+    let path = expanded.unwrap_or_else(|| path.to_path_buf());
+    if is_executable(&path) {
+        Ok(path)
+    } else if path.is_file() {
+        Err(format!("elgato_light_path {} is not executable", path.display()))
+    } else {
+        Err(format!("elgato_light_path {} does not exist", path.display()))
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+/// Oldest `elgato-light` known to accept every flag passed by this module.
+pub const MIN_ELGATO_LIGHT_VERSION: Version = Version(0, 2, 0);
+
+/// A `major.minor.patch` version reported by `elgato-light --version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32, pub u32);
+
+impl Version {
+    /// Parses the last word of `--version` output, e.g. `elgato-light 0.3.1`.
+    /// Missing minor or patch numbers count as zero; a pre-release or build
+    /// suffix is ignored.
+    fn parse(output: &str) -> Option<Self> {
+        let word = output.split_whitespace().last()?;
+        let word = word.strip_prefix('v').unwrap_or(word);
+        let core = word.split(['-', '+']).next()?;
+
+        let mut parts = core.split('.').map(|part| part.parse::<u32>().ok());
+        let major = parts.next()??;
+        let minor = parts.next().unwrap_or(Some(0))?;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        Some(Self(major, minor, patch))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Asks `elgato-light` for its version. Returns `None` if it can't be run or
+/// the output isn't recognised.
+pub fn elgato_light_version(binary: &Path) -> Option<Version> {
+    let output = Command::new(binary).arg("--version").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Version::parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn light_command(binary: &Path, light: &Light, state: LightState, selector: Selector) -> Command {
+    let mut cmd = Command::new(binary);
+    cmd.arg(state.action());
+
+    if let Some(brightness) = state.brightness(light) {
+        cmd.args(["--brightness", &brightness.to_string()]);
+        cmd.args(["--temperature", &light.temperature.to_string()]);
+    }
+    add_selector(&mut cmd, light, selector);
+    cmd
+}
+
+fn status_command(binary: &Path, light: &Light) -> Command {
+    let mut cmd = Command::new(binary);
+    cmd.arg("status");
+    add_selector(&mut cmd, light, light.selectors()[0]);
+    cmd
+}
+
+fn add_selector(cmd: &mut Command, light: &Light, selector: Selector) {
+    if let Some(name) = light.name.as_ref().filter(|_| selector != Selector::IpAddress) {
+        cmd.args(["--light", name]);
+    }
+    if let Some(ip) = light.ip_address.as_ref().filter(|_| selector != Selector::Name) {
+        cmd.args(["--ip-address", ip]);
+    }
+}
+
+/// The commands that switching `lights` to `state` would run, formatted for
+/// display.
+pub fn light_command_lines(binary: &Path, lights: &[Light], state: LightState) -> Vec<String> {
+    lights
+        .iter()
+        .map(|light| match light.http_address() {
+            Some(ip) => keylight::describe(ip, light, state),
+            None => command_line(&light_command(binary, light, state, light.selectors()[0])),
+        })
+        .collect()
+}
+
+fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if arg.contains(char::is_whitespace) || arg.is_empty() {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            } else {
+                arg.into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// First delay between retries; doubled after each failed attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// How often a running `elgato-light` is checked for having exited.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// An `elgato-light` command that was killed for running past its timeout.
+#[derive(Debug)]
+struct TimedOut(Duration);
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "elgato-light timed out after {}ms", self.0.as_millis())
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Whether `e` means the light took too long to answer, rather than that it
+/// refused.
+fn is_timeout(e: &anyhow::Error) -> bool {
+    e.is::<TimedOut>()
+        || e.chain().any(|cause| {
+            cause.downcast_ref::<std::io::Error>().is_some_and(|e| {
+                matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock)
+            })
+        })
+}
+
+/// Runs `cmd` to completion like `Command::output`, but kills it once
+/// `timeout` passes. `None` waits as long as it takes.
+fn output_with_timeout(cmd: &mut Command, timeout: Option<Duration>) -> Result<Output> {
+    let Some(timeout) = timeout else {
+        return cmd.output().context("Failed to run elgato-light");
+    };
+
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run elgato-light")?;
+
+    // elgato-light prints far less than a pipe holds, so its output can be
+    // read after it exits without it blocking on a full pipe.
+    let deadline = Instant::now() + timeout;
+    while child.try_wait().context("Failed to wait for elgato-light")?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(TimedOut(timeout).into());
+        }
+        std::thread::sleep(WAIT_POLL_INTERVAL);
+    }
+    child.wait_with_output().context("Failed to read elgato-light output")
+}
+
+/// Switches a single light to `state`, retrying failures up to
+/// `max_retries` times with exponential backoff. A command that times out is
+/// not retried, so an unreachable light can't hold up the monitor for long.
+/// A light that prefers discovery is retried by IP address once every
+/// attempt by name fails.
+pub fn run_light_command(
+    binary: &Path,
+    light: &Light,
+    state: LightState,
+    max_retries: u32,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let selectors = light.selectors();
+
+    for (i, &selector) in selectors.iter().enumerate() {
+        match run_with_selector(binary, light, state, selector, max_retries, timeout) {
+            Err(e) if i + 1 < selectors.len() => {
+                log::warn!("{}: {e:#}, falling back to its IP address", light.label());
+            }
+            result => return result,
+        }
+    }
+    Ok(())
+}
+
+fn run_with_selector(
+    binary: &Path,
+    light: &Light,
+    state: LightState,
+    selector: Selector,
+    max_retries: u32,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    if let Some(ip) = light.http_address() {
+        return with_retries(light, max_retries, || {
+            log::debug!("{}: {}", light.label(), keylight::describe(ip, light, state));
+            keylight::set(ip, light, state, timeout)
+        });
+    }
+
+    with_retries(light, max_retries, || {
+        let mut cmd = light_command(binary, light, state, selector);
+        log::debug!("{}: running {}", light.label(), command_line(&cmd));
+
+        let output = output_with_timeout(&mut cmd, timeout)?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("elgato-light {} failed: {}", state.action(), stderr.trim())
+        }
+    })
+}
+
+/// Runs `attempt_once` until it succeeds, up to `max_retries` more times with
+/// exponential backoff. A timeout is not retried.
+fn with_retries(
+    light: &Light,
+    max_retries: u32,
+    mut attempt_once: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempt = 0;
+
+    loop {
+        match attempt_once() {
+            Ok(()) => return Ok(()),
+            Err(e) if is_timeout(&e) => return Err(e),
+            Err(e) if attempt < max_retries => {
+                attempt += 1;
+                log::warn!(
+                    "{}: {e:#} (retry {attempt}/{max_retries} in {}ms)",
+                    light.label(),
+                    delay.as_millis()
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Asks `elgato-light`, or the light's HTTP API, for the light's status
+/// without changing it, to see whether the light answers.
+pub fn check_light_reachable(
+    binary: &Path,
+    light: &Light,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    if let Some(ip) = light.http_address() {
+        return keylight::is_on(ip, timeout).map(|_| ());
+    }
+    light_status(binary, light, timeout).map(|_| ())
+}
+
+/// Whether the light is on right now, per `elgato-light status` or the HTTP
+/// API, or `None` when the output doesn't say.
+pub fn light_is_on(
+    binary: &Path,
+    light: &Light,
+    timeout: Option<Duration>,
+) -> Result<Option<bool>> {
+    if let Some(ip) = light.http_address() {
+        return keylight::is_on(ip, timeout).map(Some);
+    }
+    Ok(parse_power(&light_status(binary, light, timeout)?))
+}
+
+fn light_status(binary: &Path, light: &Light, timeout: Option<Duration>) -> Result<String> {
+    let mut cmd = status_command(binary, light);
+    let output = output_with_timeout(&mut cmd, timeout)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("elgato-light status failed: {}", stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads the power state from status output such as `Light is on`,
+/// `on: true`, `Power: off` or `{"on": 0}`.
+fn parse_power(output: &str) -> Option<bool> {
+    let output = output.to_lowercase();
+    let mut words = output.split(|c: char| !c.is_ascii_alphanumeric()).filter(|w| !w.is_empty());
+    while let Some(word) = words.next() {
+        match word {
+            "off" => return Some(false),
+            "on" => {
+                return match words.next() {
+                    Some("false" | "0" | "no" | "off") => Some(false),
+                    _ => Some(true),
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Switches `lights` to `state`. Returns the labels of the lights that were
+/// switched successfully. Dimming to standby doesn't fade, since the lights
+/// are usually dimming rather than coming on.
+pub fn run_light_commands(
+    binary: &Path,
+    config: &Config,
+    lights: Vec<Light>,
+    state: LightState,
+) -> Vec<String> {
+    let timeout = config.command_timeout();
+
+    if state == LightState::On && config.fade_ms > 0 {
+        let duration = Duration::from_millis(config.fade_ms);
+        let steps = config.fade_steps;
+        fade(binary, &lights, Fade::Up, duration, steps, config.idle_temperature, timeout);
+    }
+    // Stepping down goes through `on`, so it would light up a light that is
+    // already off. Only fade when this tool last left the lights on.
+    let lit = crate::state::load().is_some_and(|s| s.light == crate::CameraState::On);
+    if state == LightState::Off && config.fade_off_ms > 0 && lit {
+        let duration = Duration::from_millis(config.fade_off_ms);
+        let steps = config.fade_steps;
+        fade(binary, &lights, Fade::Down, duration, steps, config.idle_temperature, timeout);
+    }
+
+    let mut switched = Vec::new();
+    for light in lights {
+        match run_light_command(binary, &light, state, config.max_retries, timeout) {
+            Ok(()) => switched.push(light.label().to_string()),
+            Err(e) => log::error!("Giving up on {}: {e:#}", light.label()),
+        }
+    }
+
+    switched
+}
+
+/// Fade steps closer together than this are merged, so a short fade with
+/// many steps doesn't flood the light with commands.
+const MIN_FADE_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy)]
+enum Fade {
+    /// From dim up towards the light's brightness, before turning on.
+    Up,
+    /// From the light's brightness down towards dim, before turning off.
+    Down,
+}
+
+/// Ramps every light between dim and its brightness in `steps` even
+/// increments spread over `duration`, stopping short of the end itself,
+/// which the caller sets with the usual retries. With `idle_temperature`, the
+/// temperature moves between it and the light's in the same steps. A light
+/// that fails a step is left out of the rest of the fade. Shutdown cuts the
+/// fade short.
+fn fade(
+    binary: &Path,
+    lights: &[Light],
+    direction: Fade,
+    duration: Duration,
+    steps: u32,
+    idle_temperature: Option<Temperature>,
+    timeout: Option<Duration>,
+) {
+    let most = (duration.as_millis() / MIN_FADE_INTERVAL.as_millis()).max(1);
+    let steps = steps.min(u32::try_from(most).unwrap_or(u32::MAX)).max(1);
+    let interval = duration / steps;
+    let mut fading: Vec<&Light> = lights.iter().filter(|l| l.brightness.get() > 1).collect();
+
+    for step in 1..steps {
+        if crate::shutdown_requested() {
+            return;
+        }
+
+        let level = match direction {
+            Fade::Up => step,
+            Fade::Down => steps - step,
+        };
+        fading.retain(|light| {
+            let dimmed = Light {
+                brightness: light.brightness.scaled(level, steps),
+                temperature: idle_temperature.map_or(light.temperature, |idle| {
+                    idle.towards(light.temperature, level, steps)
+                }),
+                ..(*light).clone()
+            };
+            let selector = light.selectors()[0];
+            match run_with_selector(binary, &dimmed, LightState::On, selector, 0, timeout) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::warn!("{}: {e:#}, skipping the rest of the fade", light.label());
+                    false
+                }
+            }
+        });
+        if fading.is_empty() {
+            return;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_light() -> Light {
+        Light {
+            name: Some("Key".to_string()),
+            ip_address: None,
+            brightness: Brightness::try_from(40).unwrap(),
+            temperature: Temperature::try_from(5000).unwrap(),
+            prefer_discovery: false,
+            direct_http: false,
+        }
+    }
+
+    fn args(state: LightState) -> Vec<String> {
+        let cmd = light_command(Path::new("elgato-light"), &key_light(), state, Selector::All);
+        cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn off_turns_the_light_off() {
+        assert_eq!(args(LightState::Off), ["off", "--light", "Key"]);
+    }
+
+    #[test]
+    fn standby_turns_the_light_on_at_the_standby_brightness() {
+        let standby = LightState::Standby(Brightness::try_from(5).unwrap());
+        assert_eq!(
+            args(standby),
+            ["on", "--brightness", "5", "--temperature", "5000", "--light", "Key"]
+        );
+    }
+
+    #[test]
+    fn on_turns_the_light_on_at_its_brightness() {
+        assert_eq!(
+            args(LightState::On),
+            ["on", "--brightness", "40", "--temperature", "5000", "--light", "Key"]
+        );
+    }
+
+    #[test]
+    fn timeouts_are_not_retried() {
+        let mut attempts = 0;
+        let result = with_retries(&key_light(), 3, || {
+            attempts += 1;
+            Err(TimedOut(Duration::from_secs(1)).into())
+        });
+        assert!(result.is_err_and(|e| is_timeout(&e)));
+        assert_eq!(attempts, 1);
+
+        let mut attempts = 0;
+        let result = with_retries(&key_light(), 3, || {
+            attempts += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::TimedOut)).context("Failed to read reply")
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn failures_are_retried_up_to_max_retries() {
+        let mut attempts = 0;
+        let result = with_retries(&key_light(), 2, || {
+            attempts += 1;
+            anyhow::bail!("elgato-light on failed: no light found")
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn a_later_success_is_returned() {
+        let mut attempts = 0;
+        let result = with_retries(&key_light(), 3, || {
+            attempts += 1;
+            if attempts < 2 {
+                anyhow::bail!("elgato-light on failed: no light found");
+            }
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(attempts, 2);
+    }
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("elgato-autolight-{}-{name}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn file(&self, name: &str, mode: u32) -> PathBuf {
+            self.script(name, mode, "")
+        }
+
+        fn script(&self, name: &str, mode: u32, body: &str) -> PathBuf {
+            use std::os::unix::fs::PermissionsExt;
+
+            let path = self.0.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, format!("#!/bin/sh\n{body}")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn a_flaky_binary_is_retried_until_it_succeeds() {
+        let dir = TempDir::new("flaky");
+        let calls = dir.0.join("calls");
+        let body = format!(
+            "echo \"$@\" >> '{}'\n\
+             [ \"$(wc -l < '{}')\" -ge 3 ] && exit 0\n\
+             echo 'no light found' >&2\n\
+             exit 1\n",
+            calls.display(),
+            calls.display()
+        );
+        let binary = dir.script("elgato-light", 0o755, &body);
+
+        let result = run_light_command(&binary, &key_light(), LightState::On, 2, None);
+        assert!(result.is_ok(), "{result:?}");
+        let calls = std::fs::read_to_string(&calls).unwrap();
+        assert_eq!(calls.lines().count(), 3);
+        assert!(calls.lines().all(|line| line.starts_with("on ")), "{calls}");
+    }
+
+    #[test]
+    fn configured_binary_must_be_an_executable_file() {
+        let dir = TempDir::new("configured");
+        let runnable = dir.file("runnable", 0o755);
+        let plain = dir.file("plain", 0o644);
+
+        assert_eq!(configured_binary(&runnable), Ok(runnable));
+        assert!(configured_binary(&plain).unwrap_err().ends_with("is not executable"));
+        assert!(configured_binary(&dir.0.join("missing")).unwrap_err().ends_with("does not exist"));
+        assert!(configured_binary(&dir.0).unwrap_err().ends_with("does not exist"));
+    }
+
+    #[test]
+    fn configured_binary_wins_over_path() {
+        let dir = TempDir::new("precedence");
+        let configured = dir.file("tools/elgato-light", 0o755);
+        let on_path = dir.file("bin/elgato-light", 0o755);
+        let search = dir.0.join("bin").into_os_string();
+
+        let found = find_elgato_light_in(Some(&configured), Some(&search));
+        assert_eq!(found, Ok(Some(configured)));
+        assert_eq!(find_elgato_light_in(None, Some(&search)), Ok(Some(on_path)));
+    }
+
+    #[test]
+    fn path_search_skips_files_that_cannot_run() {
+        let dir = TempDir::new("search");
+        dir.file("empty/elgato-light", 0o644);
+        let on_path = dir.file("bin/elgato-light", 0o755);
+        let search = std::env::join_paths([dir.0.join("empty"), dir.0.join("bin")]).unwrap();
+
+        assert_eq!(find_elgato_light_in(None, Some(&search)), Ok(Some(on_path)));
+    }
+
+    #[test]
+    fn unusable_configured_binary_is_an_error_not_a_search() {
+        let dir = TempDir::new("unusable");
+        let plain = dir.file("tools/elgato-light", 0o644);
+        dir.file("bin/elgato-light", 0o755);
+        let search = dir.0.join("bin").into_os_string();
+
+        let error = find_elgato_light_in(Some(&plain), Some(&search)).unwrap_err();
+        assert_eq!(error, format!("elgato_light_path {} is not executable", plain.display()));
+        let missing = dir.0.join("tools/missing");
+        let error = find_elgato_light_in(Some(&missing), Some(&search)).unwrap_err();
+        assert_eq!(error, format!("elgato_light_path {} does not exist", missing.display()));
+    }
+}
@@ -0,0 +1,98 @@
+//! Log output and size-based rotation for the service's log files.
+//!
+//! Messages go to stderr with a local ISO-8601 timestamp and level, so the
+//! files captured by launchd can be lined up with when things happened.
+//!
+//! launchd opens `StandardOutPath`/`StandardErrorPath` once and hands the
+//! descriptors to the monitor, so the files can't be renamed out from under
+//! it. Rotation copies the current file to `<name>.1` and truncates it in
+//! place instead.
+
+use log::LevelFilter;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Logged first by every run of the monitor, so a log can be split by run.
+pub const RUN_START: &str = "Starting elgato-autolight";
+
+/// How much of the end of a log file `recent_errors` reads.
+const RECENT_TAIL_BYTES: u64 = 256 * 1024;
+
+/// Installs the logger. `level` applies to this crate; dependencies only
+/// report warnings and errors.
+pub fn init(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Warn)
+        .filter_module(env!("CARGO_CRATE_NAME"), level)
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "{} {:<5} {}",
+                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f%:z"),
+                record.level(),
+                record.args()
+            )
+        })
+        .init();
+}
+
+/// Rotates `path` if it has grown past `max_bytes`, keeping `keep` old
+/// copies. Returns whether a rotation happened.
+pub fn rotate_if_needed(path: &Path, max_bytes: u64, keep: usize) -> io::Result<bool> {
+    let len = match std::fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    if len <= max_bytes {
+        return Ok(false);
+    }
+
+    if keep > 0 {
+        // Shift stdout.log.1 -> stdout.log.2, ..., dropping the oldest
+        let _ = std::fs::remove_file(backup_path(path, keep));
+        for n in (1..keep).rev() {
+            let from = backup_path(path, n);
+            if from.exists() {
+                std::fs::rename(&from, backup_path(path, n + 1))?;
+            }
+        }
+        std::fs::copy(path, backup_path(path, 1))?;
+    }
+
+    OpenOptions::new().write(true).open(path)?.set_len(0)?;
+    Ok(true)
+}
+
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+/// The last `limit` ERROR lines logged by the latest run in `path`, oldest
+/// first. A missing or unreadable file has none.
+pub fn recent_errors(path: &Path, limit: usize) -> Vec<String> {
+    let Ok(mut file) = File::open(path) else {
+        return Vec::new();
+    };
+    let len = file.metadata().map_or(0, |m| m.len());
+    let mut buf = Vec::new();
+    if file.seek(SeekFrom::Start(len.saturating_sub(RECENT_TAIL_BYTES))).is_err()
+        || file.read_to_end(&mut buf).is_err()
+    {
+        return Vec::new();
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let run = text.rfind(RUN_START).map_or(&text[..], |start| &text[start..]);
+    let errors: Vec<&str> = run
+        .lines()
+        .filter(|line| line.split_whitespace().nth(1) == Some("ERROR"))
+        .collect();
+    errors[errors.len().saturating_sub(limit)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
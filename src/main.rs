@@ -1,29 +1,316 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
-use serde::Deserialize;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, LazyLock, OnceLock};
+use std::time::{Duration, Instant};
+
+mod config_watch;
+mod desktop_notify;
+mod discover;
+mod events;
+mod history;
+mod hooks;
+mod instance;
+mod keylight;
+mod light;
+mod logging;
+mod mqtt;
+mod pause;
+mod state;
+mod units;
+
+use config_watch::ConfigWatch;
+use events::EventSocket;
+use hooks::{Hook, HookRunner};
+use instance::InstanceLock;
+use light::{
+    check_light_reachable, elgato_light_version, find_elgato_light, light_command_lines,
+    light_is_on, run_light_commands, Light, LightState, MIN_ELGATO_LIGHT_VERSION,
+};
+use log::{debug, error, info, warn};
+use mqtt::{MqttConfig, MqttPublisher};
+use units::{Brightness, Temperature};
 
 // --- Config ---
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct Config {
     #[serde(default = "default_brightness")]
-    brightness: u8,
+    brightness: Brightness,
     #[serde(default = "default_temperature")]
-    temperature: u16,
+    temperature: Temperature,
+    /// Bounds applied to every brightness the light is set to, including
+    /// scheduled and per-light values.
+    min_brightness: Option<Brightness>,
+    max_brightness: Option<Brightness>,
+    /// Dim the light to this brightness instead of turning it off when the
+    /// camera turns off. Manual `off` still turns it off.
+    standby_brightness: Option<Brightness>,
+    standby_temperature: Option<Temperature>,
+    light: Option<String>,
+    ip_address: Option<String>,
+    /// Use this `elgato-light` binary instead of searching for one.
+    elgato_light_path: Option<PathBuf>,
+    /// Refuse to start the monitor when a light can't be reached.
+    #[serde(default)]
+    require_light_at_start: bool,
+    /// Find lights by name, using `ip_address` only as a fallback.
+    #[serde(default)]
+    prefer_discovery: bool,
+    /// Switch lights that have an `ip_address` through their HTTP API
+    /// instead of running `elgato-light`.
+    #[serde(default)]
+    direct_http: bool,
+    /// How long the camera must stay in a new state before the light
+    /// follows, unless `on_delay_ms` or `off_delay_ms` says otherwise.
+    #[serde(default = "default_debounce_ms")]
+    debounce_ms: u64,
+    on_delay_ms: Option<u64>,
+    off_delay_ms: Option<u64>,
+    /// Keep the light on at least this long once it turns on.
+    #[serde(default)]
+    min_on_duration_ms: u64,
+    /// Keep the light off at least this long once it turns off.
+    #[serde(default)]
+    off_cooldown_ms: u64,
+    /// After a manual on, off or toggle, leave the light alone this long.
+    #[serde(default = "default_override_grace_ms")]
+    override_grace_ms: u64,
+    /// Ramp brightness up over this long when turning on, instead of snapping.
+    #[serde(default)]
+    fade_ms: u64,
+    /// Ramp brightness down over this long before turning off.
+    #[serde(default)]
+    fade_off_ms: u64,
+    #[serde(default = "default_fade_steps")]
+    fade_steps: u32,
+    /// The temperature fades start from when turning on and end at when
+    /// turning off. Unset fades keep each light's temperature.
+    idle_temperature: Option<Temperature>,
+    #[serde(default)]
+    notify: bool,
+    log_predicate: Option<Predicates>,
+    /// Text that marks a matched log line as the camera turning on or off,
+    /// in place of the PowerLog state field. Set both or neither.
+    camera_on_match: Option<String>,
+    camera_off_match: Option<String>,
+    /// Turn the light off while the screen is locked and back on at unlock if
+    /// the camera is still on.
+    #[serde(default)]
+    react_to_lock: bool,
+    lock_predicate: Option<String>,
+    /// Re-check the camera and set the light to match when the Mac wakes.
+    #[serde(default)]
+    reconcile_on_wake: bool,
+    wake_predicate: Option<String>,
+    /// Reload the config as soon as the file changes. Unset means only when
+    /// running in a terminal; the service relies on `reload-config`.
+    watch_config: Option<bool>,
+    event_socket: Option<PathBuf>,
+    /// Publish each transition to an MQTT broker.
+    mqtt: Option<MqttConfig>,
+    /// Commands run with `/bin/sh` each time the light follows the camera on
+    /// or off.
+    on_camera_on: Option<String>,
+    on_camera_off: Option<String>,
+    /// Kill a hook that runs longer than this. 0 waits indefinitely.
+    #[serde(default = "default_hook_timeout_ms")]
+    hook_timeout_ms: u64,
+    /// Also accepted as `command_retries`.
+    #[serde(default = "default_max_retries", alias = "command_retries")]
+    max_retries: u32,
+    /// Restart the camera log stream when it prints nothing for this long.
+    /// 0 never does.
+    #[serde(default)]
+    stream_idle_timeout_ms: u64,
+    /// Exit when the camera log stream fails this many times in a row. 0
+    /// keeps restarting it forever.
+    #[serde(default = "default_max_stream_failures")]
+    max_stream_failures: u32,
+    /// Kill an elgato-light command that runs longer than this. 0 waits
+    /// indefinitely.
+    #[serde(default = "default_command_timeout_ms")]
+    command_timeout_ms: u64,
+    #[serde(default = "default_max_log_bytes")]
+    max_log_bytes: u64,
+    #[serde(default = "default_max_log_files")]
+    max_log_files: usize,
+    /// A nightly window in which the camera turning on leaves the light off.
+    quiet_hours: Option<QuietHours>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    app_allowlist: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    app_blocklist: Vec<String>,
+    /// Camera device IDs that switch the light. Empty means every camera.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    camera_allowlist: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    schedule: Vec<ScheduleEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    lights: Vec<LightConfig>,
+    /// Brightness and temperature for the camera apps named by the keys.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// A time of day, written as `"HH:MM"` in the config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+struct TimeOfDay {
+    /// Minutes since midnight.
+    minutes: u16,
+}
+
+impl TimeOfDay {
+    fn now() -> Self {
+        use chrono::Timelike;
+        let now = chrono::Local::now();
+        Self {
+            minutes: (now.hour() * 60 + now.minute()) as u16,
+        }
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time '{value}', expected HH:MM");
+        let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+        let hours: u16 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            minutes: hours * 60 + minutes,
+        })
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl std::fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.minutes / 60, self.minutes % 60)
+    }
+}
+
+/// Whether `time` falls within `[start, end)`. A range whose end is before
+/// its start wraps past midnight.
+fn time_in_range(time: TimeOfDay, start: TimeOfDay, end: TimeOfDay) -> bool {
+    if start <= end {
+        start <= time && time < end
+    } else {
+        time >= start || time < end
+    }
+}
+
+/// The `quiet_hours` table. `end` before `start` wraps past midnight.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+struct QuietHours {
+    start: TimeOfDay,
+    end: TimeOfDay,
+}
+
+impl std::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// One entry of the `[[schedule]]` array, overriding the top-level brightness
+/// and temperature between `start` and `end`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct ScheduleEntry {
+    start: TimeOfDay,
+    end: TimeOfDay,
+    brightness: Option<Brightness>,
+    temperature: Option<Temperature>,
+}
+
+/// One entry of the `[[lights]]` array. Unset brightness/temperature fall back
+/// to the top-level values.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct LightConfig {
     light: Option<String>,
     ip_address: Option<String>,
+    brightness: Option<Brightness>,
+    temperature: Option<Temperature>,
+}
+
+/// A `[profiles.<app>]` table, used while that app has the camera on.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct Profile {
+    brightness: Option<Brightness>,
+    temperature: Option<Temperature>,
+}
+
+/// `log_predicate` as a single predicate, or a list of them with one `log
+/// stream` each, so events from several subsystems can be watched at once.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Predicates {
+    One(String),
+    Many(Vec<String>),
+}
+
+const DEFAULT_LOG_PREDICATE: &str =
+    "subsystem == \"com.apple.UVCExtension\" and composedMessage contains \"Post PowerLog\"";
+
+const DEFAULT_LOCK_PREDICATE: &str = "process == \"loginwindow\" and \
+    (eventMessage contains \"com.apple.screenIsLocked\" or \
+    eventMessage contains \"com.apple.screenIsUnlocked\")";
+
+const DEFAULT_WAKE_PREDICATE: &str =
+    "process == \"powerd\" and eventMessage contains \"Wake from\"";
+
+fn default_brightness() -> Brightness {
+    Brightness::try_from(10).expect("default brightness is in range")
+}
+fn default_temperature() -> Temperature {
+    Temperature::try_from(5000).expect("default temperature is in range")
 }
 
-fn default_brightness() -> u8 {
+fn default_max_retries() -> u32 {
+    3
+}
+fn default_max_stream_failures() -> u32 {
+    5
+}
+fn default_command_timeout_ms() -> u64 {
+    10_000
+}
+fn default_hook_timeout_ms() -> u64 {
+    10_000
+}
+fn default_debounce_ms() -> u64 {
+    500
+}
+fn default_override_grace_ms() -> u64 {
+    5 * 60 * 1000
+}
+fn default_fade_steps() -> u32 {
     10
 }
-fn default_temperature() -> u16 {
-    5000
+fn default_max_log_bytes() -> u64 {
+    5 * 1024 * 1024
+}
+fn default_max_log_files() -> usize {
+    3
 }
 
 impl Default for Config {
@@ -31,16 +318,362 @@ impl Default for Config {
         Self {
             brightness: default_brightness(),
             temperature: default_temperature(),
+            min_brightness: None,
+            max_brightness: None,
+            standby_brightness: None,
+            standby_temperature: None,
             light: None,
             ip_address: None,
+            elgato_light_path: None,
+            require_light_at_start: false,
+            prefer_discovery: false,
+            direct_http: false,
+            debounce_ms: default_debounce_ms(),
+            on_delay_ms: None,
+            off_delay_ms: None,
+            min_on_duration_ms: 0,
+            off_cooldown_ms: 0,
+            override_grace_ms: default_override_grace_ms(),
+            fade_ms: 0,
+            fade_off_ms: 0,
+            fade_steps: default_fade_steps(),
+            idle_temperature: None,
+            notify: false,
+            log_predicate: None,
+            camera_on_match: None,
+            camera_off_match: None,
+            react_to_lock: false,
+            lock_predicate: None,
+            reconcile_on_wake: false,
+            wake_predicate: None,
+            watch_config: None,
+            event_socket: None,
+            mqtt: None,
+            on_camera_on: None,
+            on_camera_off: None,
+            hook_timeout_ms: default_hook_timeout_ms(),
+            max_retries: default_max_retries(),
+            stream_idle_timeout_ms: 0,
+            max_stream_failures: default_max_stream_failures(),
+            command_timeout_ms: default_command_timeout_ms(),
+            max_log_bytes: default_max_log_bytes(),
+            max_log_files: default_max_log_files(),
+            quiet_hours: None,
+            app_allowlist: Vec::new(),
+            app_blocklist: Vec::new(),
+            camera_allowlist: Vec::new(),
+            schedule: Vec::new(),
+            lights: Vec::new(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Checks the settings that parsing can't, such as how two values relate;
+    /// brightness and temperature ranges are enforced by their types. Returns
+    /// every problem found rather than stopping at the first.
+    fn validate(&self) -> Vec<String> {
+        self.problems().into_iter().map(|(_, problem)| problem).collect()
+    }
+
+    /// Each problem `validate` reports, with the keys it involves.
+    fn problems(&self) -> Vec<(&'static [&'static str], String)> {
+        let mut problems = Vec::new();
+
+        if let (Some(min), Some(max)) = (self.min_brightness, self.max_brightness) {
+            if min > max {
+                let problem = format!("min_brightness {min} is above max_brightness {max}");
+                problems.push((&["min_brightness", "max_brightness"][..], problem));
+            }
+        }
+        if let Some(ref mqtt) = self.mqtt {
+            if mqtt.broker.is_empty() || mqtt.topic.is_empty() {
+                problems.push((&["mqtt"][..], "mqtt needs both a broker and a topic".to_string()));
+            }
+        }
+        if self.fade_steps == 0 {
+            problems.push((&["fade_steps"][..], "fade_steps must be at least 1".to_string()));
+        }
+        if self.camera_on_match.is_some() != self.camera_off_match.is_some() {
+            let problem = "camera_on_match and camera_off_match must be set together".to_string();
+            problems.push((&["camera_on_match", "camera_off_match"][..], problem));
         }
+        if self.quiet_hours.is_some_and(|quiet| quiet.start == quiet.end) {
+            let problem = "quiet_hours start and end must differ".to_string();
+            problems.push((&["quiet_hours"][..], problem));
+        }
+        if matches!(self.log_predicate, Some(Predicates::Many(ref list)) if list.is_empty()) {
+            let problem = "log_predicate must list at least one predicate".to_string();
+            problems.push((&["log_predicate"][..], problem));
+        }
+
+        problems
+    }
+
+    /// The lights to control. Falls back to the single-light `light` and
+    /// `ip_address` fields when no `[[lights]]` entries are configured.
+    ///
+    /// Lights without their own brightness or temperature use the schedule
+    /// entry active right now, or the top-level values if none matches.
+    fn resolved_lights(&self) -> Vec<Light> {
+        self.lights_for_app(None)
+    }
+
+    /// The config as shown to the user, without the MQTT password. The file
+    /// itself keeps it.
+    fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        if let Some(ref mut mqtt) = config.mqtt {
+            mqtt.password = None;
+        }
+        config
+    }
+
+    /// Whether `light` or `ip_address` is set alongside `[[lights]]`, which
+    /// replaces them.
+    fn ignores_top_level_light(&self) -> bool {
+        !self.lights.is_empty() && (self.light.is_some() || self.ip_address.is_some())
+    }
+
+    /// Whether any light is switched with `elgato-light`, rather than all of
+    /// them over HTTP.
+    fn needs_elgato_light(&self) -> bool {
+        self.resolved_lights().iter().any(Light::uses_elgato_light)
+    }
+
+    /// Like `resolved_lights`, but with the profile for `app`, if there is
+    /// one, ahead of the schedule and top-level values.
+    fn lights_for_app(&self, app: Option<&str>) -> Vec<Light> {
+        let (mut brightness, mut temperature) = self.scheduled_settings(TimeOfDay::now());
+        if let Some((_, profile)) = self.profile(app) {
+            brightness = profile.brightness.unwrap_or(brightness);
+            temperature = profile.temperature.unwrap_or(temperature);
+        }
+
+        let mut lights: Vec<Light> = if self.lights.is_empty() {
+            vec![Light {
+                name: self.light.clone(),
+                ip_address: self.ip_address.clone(),
+                brightness,
+                temperature,
+                prefer_discovery: self.prefer_discovery,
+                direct_http: self.direct_http,
+            }]
+        } else {
+            self.lights
+                .iter()
+                .map(|l| Light {
+                    name: l.light.clone(),
+                    ip_address: l.ip_address.clone(),
+                    brightness: l.brightness.unwrap_or(brightness),
+                    temperature: l.temperature.unwrap_or(temperature),
+                    prefer_discovery: self.prefer_discovery,
+                    direct_http: self.direct_http,
+                })
+                .collect()
+        };
+
+        for light in &mut lights {
+            let clamped = self.clamp_brightness(light.label(), light.brightness);
+            light.brightness = clamped;
+        }
+        lights
+    }
+
+    /// The resolved lights whose name or IP address is one of `names`, or
+    /// every light when `names` is empty. Names are matched ignoring case.
+    fn selected_lights(&self, names: &[String]) -> Result<Vec<Light>> {
+        let lights = self.resolved_lights();
+        if names.is_empty() {
+            return Ok(lights);
+        }
+
+        let matches = |light: &Light, name: &str| {
+            [&light.name, &light.ip_address]
+                .into_iter()
+                .flatten()
+                .any(|id| id.eq_ignore_ascii_case(name))
+        };
+        let unknown: Vec<String> = names
+            .iter()
+            .filter(|name| !lights.iter().any(|light| matches(light, name)))
+            .map(|name| format!("'{name}'"))
+            .collect();
+        if !unknown.is_empty() {
+            let known: Vec<&str> = lights.iter().map(Light::label).collect();
+            let message = format!(
+                "No configured light named {}. Configured lights: {}",
+                unknown.join(", "),
+                known.join(", ")
+            );
+            return Err(ExitCode::Config.error(message).into());
+        }
+
+        Ok(lights
+            .into_iter()
+            .filter(|light| names.iter().any(|name| matches(light, name)))
+            .collect())
+    }
+
+    /// The standby state and the lights to dim to it, or `None` when the
+    /// camera turning off should turn them off, which a standby brightness of
+    /// 0 also means. Without `standby_temperature`, each light keeps the
+    /// temperature it would have when on.
+    fn standby_lights(&self) -> Option<(LightState, Vec<Light>)> {
+        let brightness = self.standby_brightness.filter(|&b| b > Brightness::MIN)?;
+        let lights = self
+            .resolved_lights()
+            .into_iter()
+            .map(|light| Light {
+                temperature: self.standby_temperature.unwrap_or(light.temperature),
+                ..light
+            })
+            .collect();
+        let state = LightState::Standby(self.clamp_brightness("Standby", brightness));
+        Some((state, lights))
+    }
+
+    /// Keeps `brightness` within `min_brightness` and `max_brightness`, which
+    /// win over every other brightness setting.
+    fn clamp_brightness(&self, label: &str, brightness: Brightness) -> Brightness {
+        let clamped = brightness
+            .max(self.min_brightness.unwrap_or(Brightness::MIN))
+            .min(self.max_brightness.unwrap_or(Brightness::MAX));
+        if clamped != brightness {
+            debug!("{label}: brightness {brightness}% clamped to {clamped}%");
+        }
+        clamped
+    }
+
+    /// Brightness and temperature at `time`, taken from the first schedule
+    /// entry covering it and falling back to the top-level values.
+    fn scheduled_settings(&self, time: TimeOfDay) -> (Brightness, Temperature) {
+        let entry = self
+            .schedule
+            .iter()
+            .find(|entry| time_in_range(time, entry.start, entry.end));
+
+        (
+            entry.and_then(|e| e.brightness).unwrap_or(self.brightness),
+            entry.and_then(|e| e.temperature).unwrap_or(self.temperature),
+        )
+    }
+
+    /// The quiet hours, if `time` falls within them.
+    fn quiet_hours_at(&self, time: TimeOfDay) -> Option<QuietHours> {
+        self.quiet_hours.filter(|quiet| time_in_range(time, quiet.start, quiet.end))
+    }
+
+    /// The profile whose name matches `app`, ignoring case, with its name.
+    fn profile(&self, app: Option<&str>) -> Option<(&str, &Profile)> {
+        let app = app?;
+        self.profiles
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(app))
+            .map(|(name, profile)| (name.as_str(), profile))
+    }
+
+    /// Whether an event from `app` should switch the light. An unknown app
+    /// never matches the allowlist but is never blocked either.
+    fn app_allowed(&self, app: Option<&str>) -> bool {
+        let listed = |list: &[String]| {
+            app.is_some_and(|app| list.iter().any(|a| a.eq_ignore_ascii_case(app)))
+        };
+
+        if !self.app_allowlist.is_empty() && !listed(&self.app_allowlist) {
+            return false;
+        }
+        !listed(&self.app_blocklist)
+    }
+
+    /// Whether an event from the camera `device` should switch the light. As
+    /// with apps, an unknown device never matches the allowlist.
+    fn camera_allowed(&self, device: Option<&str>) -> bool {
+        self.camera_allowlist.is_empty()
+            || device.is_some_and(|device| {
+                self.camera_allowlist.iter().any(|d| d.eq_ignore_ascii_case(device))
+            })
+    }
+
+    fn on_delay(&self) -> Duration {
+        Duration::from_millis(self.on_delay_ms.unwrap_or(self.debounce_ms))
+    }
+
+    fn off_delay(&self) -> Duration {
+        Duration::from_millis(self.off_delay_ms.unwrap_or(self.debounce_ms))
+    }
+
+    fn command_timeout(&self) -> Option<Duration> {
+        (self.command_timeout_ms > 0).then(|| Duration::from_millis(self.command_timeout_ms))
+    }
+
+    fn hook(&self, state: CameraState) -> Option<&str> {
+        match state {
+            CameraState::On => self.on_camera_on.as_deref(),
+            CameraState::Off => self.on_camera_off.as_deref(),
+        }
+    }
+
+    fn log_predicates(&self) -> Vec<&str> {
+        match self.log_predicate {
+            Some(Predicates::One(ref predicate)) => vec![predicate],
+            Some(Predicates::Many(ref list)) if !list.is_empty() => {
+                list.iter().map(String::as_str).collect()
+            }
+            _ => vec![DEFAULT_LOG_PREDICATE],
+        }
+    }
+
+    /// The camera state a log line reports, using `camera_on_match` and
+    /// `camera_off_match` when set. When both match, the longer one wins, so
+    /// `= On` doesn't shadow `= Online`.
+    fn camera_state(&self, line: &str) -> Option<CameraState> {
+        let (Some(on), Some(off)) = (&self.camera_on_match, &self.camera_off_match) else {
+            return parse_camera_state(line);
+        };
+        let mut patterns = [(on, CameraState::On), (off, CameraState::Off)];
+        patterns.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.len()));
+        patterns
+            .into_iter()
+            .find(|(pattern, _)| line.contains(pattern.as_str()))
+            .map(|(_, state)| state)
+    }
+
+    /// The camera predicates joined into one, for `log show`.
+    fn combined_log_predicate(&self) -> String {
+        match self.log_predicates()[..] {
+            [predicate] => predicate.to_string(),
+            ref list => list.iter().map(|p| format!("({p})")).collect::<Vec<_>>().join(" or "),
+        }
+    }
+
+    fn lock_predicate(&self) -> &str {
+        self.lock_predicate.as_deref().unwrap_or(DEFAULT_LOCK_PREDICATE)
     }
+
+    fn wake_predicate(&self) -> &str {
+        self.wake_predicate.as_deref().unwrap_or(DEFAULT_WAKE_PREDICATE)
+    }
+}
+
+/// Set from `--config` or `ELGATO_AUTOLIGHT_CONFIG`, replacing the default
+/// location for every command.
+static CONFIG_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// The user's home directory. An empty `HOME` counts as unset.
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
 }
 
 fn config_path() -> Option<PathBuf> {
-    std::env::var("HOME")
-        .ok()
-        .map(|home| PathBuf::from(home).join(".config/elgato-autolight/config.toml"))
+    if let Some(path) = CONFIG_OVERRIDE.get() {
+        return Some(path.clone());
+    }
+
+    home_dir().map(|home| home.join(".config/elgato-autolight/config.toml"))
 }
 
 fn load_config() -> Config {
@@ -48,53 +681,357 @@ fn load_config() -> Config {
         return Config::default();
     };
 
-    match std::fs::read_to_string(&path) {
-        Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
-            eprintln!("Warning: failed to parse {}: {}", path.display(), e);
-            Config::default()
+    let table: toml::Table = match std::fs::read_to_string(&path) {
+        Ok(contents) => match toml::from_str(&contents) {
+            Ok(table) => table,
+            Err(e) => {
+                warn!("Failed to parse {}: {}", path.display(), e);
+                return Config::default();
+            }
+        },
+        Err(_) => return Config::default(),
+    };
+    config_from_table(table, &path)
+}
+
+/// Builds the config from a parsed file, warning about and dropping each
+/// invalid setting.
+fn config_from_table(mut table: toml::Table, path: &Path) -> Config {
+    // An invalid setting only costs its own value: it is reported and
+    // dropped, and the rest of the file still applies. Each pass drops at
+    // least one key, so this ends.
+    loop {
+        let config = match Config::deserialize(table.clone()) {
+            Ok(config) => config,
+            Err(e) => {
+                let invalid: Vec<(String, String)> = table
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let single = toml::Table::from_iter([(key.clone(), value.clone())]);
+                        let e = Config::deserialize(single).err()?;
+                        Some((key.clone(), e.message().to_string()))
+                    })
+                    .collect();
+                if invalid.is_empty() {
+                    warn!("Invalid settings in {}: {}", path.display(), e.message());
+                    warn!("Using default settings instead.");
+                    return Config::default();
+                }
+                for (key, problem) in invalid {
+                    table.remove(&key);
+                    let problem = if problem.starts_with(key.as_str()) {
+                        problem
+                    } else {
+                        format!("{key}: {problem}")
+                    };
+                    warn!("Invalid setting in {}: {problem}, using the default", path.display());
+                }
+                continue;
+            }
+        };
+
+        let problems = config.problems();
+        if problems.is_empty() {
+            return config;
+        }
+        for (keys, problem) in problems {
+            for key in keys {
+                table.remove(*key);
+            }
+            let keys = keys.join(" and ");
+            warn!("Invalid setting in {}: {problem}, using the default {keys}", path.display());
+        }
+    }
+}
+
+/// Lists the settings that differ between two configs, e.g.
+/// `brightness: 10 -> 30`.
+fn config_changes(old: &Config, new: &Config) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old.redacted()), serde_json::to_value(new.redacted()))
+    else {
+        return Vec::new();
+    };
+
+    let show = |value: Option<&serde_json::Value>| match value {
+        None | Some(serde_json::Value::Null) => "unset".to_string(),
+        Some(value) => value.to_string(),
+    };
+
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| format!("{key}: {} -> {}", show(old.get(key)), show(new.get(key))))
+        .collect()
+}
+
+/// Like `load_config`, but fails on parse errors instead of falling back to
+/// defaults, so a broken file is never silently overwritten.
+fn read_config(path: &Path) -> Result<Config> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).with_context(|| {
+            ExitCode::Config.error(format!("Failed to parse {}", path.display()))
         }),
-        Err(_) => Config::default(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn save_config(path: &Path, config: &Config) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    let contents = toml::to_string(config).context("Failed to serialize config")?;
+    std::fs::write(path, contents)
+        .with_context(|| format!("Failed to write config to {}", path.display()))
+}
+
+// --- Config command ---
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ConfigKey {
+    Brightness,
+    Temperature,
+    Light,
+    #[value(name = "ip_address")]
+    IpAddress,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+fn required_config_path() -> Result<PathBuf> {
+    config_path().ok_or_else(|| anyhow::anyhow!("HOME not set, cannot locate config file"))
+}
+
+fn config_get(key: ConfigKey) -> Result<()> {
+    let config = read_config(&required_config_path()?)?;
+
+    let value = match key {
+        ConfigKey::Brightness => Some(config.brightness.to_string()),
+        ConfigKey::Temperature => Some(config.temperature.to_string()),
+        ConfigKey::Light => config.light,
+        ConfigKey::IpAddress => config.ip_address,
+    };
+
+    if let Some(value) = value {
+        println!("{value}");
+    }
+    Ok(())
+}
+
+fn config_set(key: ConfigKey, value: &str) -> Result<()> {
+    let path = required_config_path()?;
+    config_set_at(&path, key, value)?;
+    println!("Updated {}", path.display());
+    Ok(())
+}
+
+/// Sets `key` in the config file at `path`, leaving every other setting as
+/// it is.
+fn config_set_at(path: &Path, key: ConfigKey, value: &str) -> Result<()> {
+    let mut config = read_config(path)?;
+
+    match key {
+        ConfigKey::Brightness => {
+            config.brightness = value.parse().map_err(|e: String| ExitCode::Config.error(e))?;
+        }
+        ConfigKey::Temperature => {
+            config.temperature = value.parse().map_err(|e: String| ExitCode::Config.error(e))?;
+        }
+        ConfigKey::Light => config.light = Some(value.to_string()),
+        ConfigKey::IpAddress => config.ip_address = Some(value.to_string()),
+    }
+    save_config(path, &config)
+}
+
+/// Prints the path alone on stdout, so it can be used in scripts, and notes
+/// on stderr when there is no file there yet.
+fn config_show_path() -> Result<()> {
+    let path = required_config_path()?;
+    println!("{}", path.display());
+    if !path.exists() {
+        eprintln!("(no file there yet, so the defaults are in use)");
+    }
+    Ok(())
+}
+
+/// Prints the settings the monitor would use right now as TOML or JSON. Unlike
+/// `config export`, invalid settings are shown with the defaults that
+/// replace them instead of failing.
+fn config_show(format: ConfigFormat) -> Result<()> {
+    let config = load_config().redacted();
+    match format {
+        ConfigFormat::Toml => {
+            print!("{}", toml::to_string(&config).context("Failed to serialize config")?);
+        }
+        ConfigFormat::Json => println!("{}", serde_json::to_string_pretty(&config)?),
+    }
+    Ok(())
+}
+
+/// Prints the config file's settings, with defaults filled in, so they can be
+/// copied to another machine.
+fn config_export(json: bool) -> Result<()> {
+    let config = read_config(&required_config_path()?)?.redacted();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&config)?);
+    } else {
+        print!("{}", toml::to_string(&config).context("Failed to serialize config")?);
+    }
+    Ok(())
+}
+
+/// Replaces the config file with settings read from `source`, or stdin when
+/// it is `None` or `-`. JSON is accepted as well as TOML. Nothing is written
+/// unless every setting is valid.
+fn config_import(source: Option<&Path>) -> Result<()> {
+    let contents = match source.filter(|p| *p != Path::new("-")) {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?,
+        None => {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .context("Failed to read config from stdin")?;
+            contents
+        }
+    };
+
+    let config: Config = if contents.trim_start().starts_with('{') {
+        serde_json::from_str(&contents)
+            .context(ExitCode::Config.error("Failed to parse imported config as JSON"))?
+    } else {
+        toml::from_str(&contents)
+            .context(ExitCode::Config.error("Failed to parse imported config as TOML"))?
+    };
+
+    let problems = config.validate();
+    if !problems.is_empty() {
+        let message = format!("Imported config is invalid:\n  {}", problems.join("\n  "));
+        return Err(ExitCode::Config.error(message).into());
     }
+
+    let path = required_config_path()?;
+    save_config(&path, &config)?;
+    println!("Imported config to {}", path.display());
+    Ok(())
 }
 
 // --- LaunchAgent ---
 
 const LABEL: &str = "com.wassimk.elgato-autolight";
 
-fn plist_path() -> PathBuf {
-    let home = std::env::var("HOME").expect("HOME not set");
-    PathBuf::from(home).join("Library/LaunchAgents/com.wassimk.elgato-autolight.plist")
+/// The label `brew services` gives the formula's service.
+const HOMEBREW_LABEL: &str = "homebrew.mxcl.elgato-autolight";
+
+fn plist_path() -> Result<PathBuf> {
+    let home = home_dir().context("HOME not set, cannot locate LaunchAgent plist")?;
+    Ok(home.join("Library/LaunchAgents/com.wassimk.elgato-autolight.plist"))
+}
+
+fn homebrew_plist_path() -> Result<PathBuf> {
+    let home = home_dir().context("HOME not set, cannot locate LaunchAgent plist")?;
+    Ok(home.join(format!("Library/LaunchAgents/{HOMEBREW_LABEL}.plist")))
+}
+
+/// Whether the monitor runs under `brew services` instead of the agent that
+/// `install` writes. The agent from `install` wins if both exist.
+fn homebrew_managed() -> Result<bool> {
+    Ok(!plist_path()?.exists() && homebrew_plist_path()?.exists())
+}
+
+/// The label of the agent that runs the monitor.
+fn service_label() -> Result<&'static str> {
+    Ok(if homebrew_managed()? { HOMEBREW_LABEL } else { LABEL })
+}
+
+/// The plist of the agent that runs the monitor.
+fn service_plist() -> Result<PathBuf> {
+    if homebrew_managed()? {
+        homebrew_plist_path()
+    } else {
+        plist_path()
+    }
+}
+
+/// Hands a lifecycle action to `brew services` for a Homebrew-managed monitor.
+fn brew_services(action: &str) -> Result<()> {
+    let output = Command::new("brew")
+        .args(["services", action, "elgato-autolight"])
+        .output()
+        .context(ExitCode::Launchctl.error(format!("Failed to run brew services {action}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = format!("brew services {action} failed: {}", stderr.trim());
+        return Err(ExitCode::Launchctl.error(message).into());
+    }
+    Ok(())
+}
+
+fn log_dir() -> Result<PathBuf> {
+    let home = home_dir().context("HOME not set, cannot locate log directory")?;
+    Ok(home.join("Library/Logs/elgato-autolight"))
 }
 
-fn log_dir() -> PathBuf {
-    let home = std::env::var("HOME").expect("HOME not set");
-    PathBuf::from(home).join("Library/Logs/elgato-autolight")
+fn current_uid() -> Result<String> {
+    let output = Command::new("id").arg("-u").output().context("Failed to run id -u")?;
+    if !output.status.success() {
+        anyhow::bail!("id -u failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
-fn current_uid() -> String {
-    let output = Command::new("id").arg("-u").output().expect("failed to run id -u");
-    String::from_utf8_lossy(&output.stdout).trim().to_string()
+/// launchd lifecycle settings written into the plist.
+struct ServiceOptions {
+    /// Restart the monitor whenever it exits.
+    keep_alive: bool,
+    /// Start the monitor as soon as the agent is loaded.
+    run_at_load: bool,
 }
 
-fn generate_plist(binary_path: &str) -> String {
-    let log_dir = log_dir();
+/// Builds the LaunchAgent plist. Everything that depends on the environment
+/// is passed in, so nothing here reads HOME or the filesystem.
+fn generate_plist(
+    label: &str,
+    binary_path: &str,
+    config_path: Option<&Path>,
+    log_dir: &Path,
+    options: &ServiceOptions,
+) -> String {
     let stdout_log = log_dir.join("stdout.log");
     let stderr_log = log_dir.join("stderr.log");
 
+    let config_args = config_path.map_or(String::new(), |path| {
+        format!(
+            "\n        <string>--config</string>\n        <string>{}</string>",
+            xml_escape(&path.display().to_string())
+        )
+    });
+
     format!(
         r#"<?xml version="1.0" encoding="UTF-8"?>
 <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
 <plist version="1.0">
 <dict>
     <key>Label</key>
-    <string>{LABEL}</string>
+    <string>{label}</string>
     <key>ProgramArguments</key>
     <array>
-        <string>{binary_path}</string>
+        <string>{binary_path}</string>{config_args}
         <string>start</string>
     </array>
     <key>KeepAlive</key>
-    <true/>
+    <{keep_alive}/>{run_at_load}
     <key>StandardOutPath</key>
     <string>{stdout}</string>
     <key>StandardErrorPath</key>
@@ -106,65 +1043,136 @@ fn generate_plist(binary_path: &str) -> String {
     </dict>
 </dict>
 </plist>"#,
-        stdout = stdout_log.display(),
-        stderr = stderr_log.display(),
+        keep_alive = options.keep_alive,
+        run_at_load = if options.run_at_load {
+            "\n    <key>RunAtLoad</key>\n    <true/>"
+        } else {
+            ""
+        },
+        label = xml_escape(label),
+        binary_path = xml_escape(binary_path),
+        stdout = xml_escape(&stdout_log.display().to_string()),
+        stderr = xml_escape(&stderr_log.display().to_string()),
     )
 }
 
-fn install_launchagent(force: bool) -> Result<()> {
-    let plist = plist_path();
+/// Escapes `value` for a plist `<string>`, so a path containing `&` or `<`
+/// doesn't make the file malformed.
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
 
-    if plist.exists() && !force {
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads the program path, the first `ProgramArguments` entry, out of a plist
+/// written by `generate_plist`.
+fn plist_binary_path(plist: &str) -> Option<String> {
+    let (_, rest) = plist.split_once("<key>ProgramArguments</key>")?;
+    let (_, rest) = rest.split_once("<string>")?;
+    let (path, _) = rest.split_once("</string>")?;
+    Some(xml_unescape(path.trim()))
+}
+
+fn install_launchagent(force: bool, options: ServiceOptions) -> Result<()> {
+    let plist = plist_path()?;
+    if homebrew_managed()? {
         anyhow::bail!(
-            "LaunchAgent already installed at {}\nUse --force to overwrite.",
-            plist.display()
+            "The monitor is already managed by brew services.\n\
+             Run `brew services stop elgato-autolight` first to switch to the LaunchAgent."
         );
     }
 
+    let log_dir = log_dir()?;
+    let uid = current_uid()?;
+
     let binary_path = std::env::current_exe()
         .context("Failed to determine binary path")?
         .to_string_lossy()
         .to_string();
 
+    // An upgrade can move the binary; rewrite a plist that points elsewhere
+    // rather than leaving the service launching a path that's gone.
+    if plist.exists() && !force {
+        let installed = std::fs::read_to_string(&plist)
+            .ok()
+            .and_then(|content| plist_binary_path(&content));
+        match installed {
+            Some(ref installed) if *installed != binary_path => {
+                println!("LaunchAgent runs {installed}, updating it to {binary_path}");
+            }
+            _ => {
+                println!("LaunchAgent already installed at {}", plist.display());
+                println!("Use --force to overwrite.");
+                return Ok(());
+            }
+        }
+    }
+
     // Unload existing agent if overwriting
     if plist.exists() {
         let _ = Command::new("launchctl")
-            .args(["bootout", &format!("gui/{}/{LABEL}", current_uid())])
+            .args(["bootout", &format!("gui/{uid}/{LABEL}")])
             .output();
     }
 
     // Create log directory
-    std::fs::create_dir_all(log_dir()).context("Failed to create log directory")?;
+    std::fs::create_dir_all(&log_dir).context("Failed to create log directory")?;
 
     // Write plist
-    let content = generate_plist(&binary_path);
+    // Bake an overridden config path in so the service reads the same file
+    let config_path = CONFIG_OVERRIDE
+        .get()
+        .map(std::path::absolute)
+        .transpose()
+        .context("Failed to resolve config path")?;
+
+    let content = generate_plist(LABEL, &binary_path, config_path.as_deref(), &log_dir, &options);
     std::fs::write(&plist, content)
         .with_context(|| format!("Failed to write plist to {}", plist.display()))?;
 
     // Load agent
     let output = Command::new("launchctl")
-        .args(["bootstrap", &format!("gui/{}", current_uid()), &plist.to_string_lossy()])
+        .args(["bootstrap", &format!("gui/{uid}"), &plist.to_string_lossy()])
         .output()
-        .context("Failed to run launchctl bootstrap")?;
+        .context(ExitCode::Launchctl.error("Failed to run launchctl bootstrap"))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("launchctl bootstrap failed: {}", stderr.trim());
+        let message = format!("launchctl bootstrap failed: {}", stderr.trim());
+        return Err(ExitCode::Launchctl.error(message).into());
     }
 
     println!("LaunchAgent installed and loaded.");
     println!("  Plist: {}", plist.display());
-    println!("  Logs:  {}", log_dir().display());
+    println!("  Logs:  {}", log_dir.display());
     Ok(())
 }
 
 fn uninstall_launchagent() -> Result<()> {
+    if homebrew_managed()? {
+        println!("The monitor is managed by brew services.");
+        println!("Run `brew services stop elgato-autolight` to remove it.");
+        return Ok(());
+    }
+    let plist = plist_path()?;
+
     // Unload (ignore errors if not loaded)
     let _ = Command::new("launchctl")
-        .args(["bootout", &format!("gui/{}/{LABEL}", current_uid())])
+        .args(["bootout", &format!("gui/{}/{LABEL}", current_uid()?)])
         .output();
 
-    let plist = plist_path();
     if plist.exists() {
         std::fs::remove_file(&plist)
             .with_context(|| format!("Failed to remove {}", plist.display()))?;
@@ -177,12 +1185,17 @@ fn uninstall_launchagent() -> Result<()> {
 }
 
 fn stop_launchagent() -> Result<()> {
-    let target = format!("gui/{}/{LABEL}", current_uid());
+    if homebrew_managed()? {
+        brew_services("stop")?;
+        println!("Service stopped.");
+        return Ok(());
+    }
+    let target = format!("gui/{}/{LABEL}", current_uid()?);
 
     let output = Command::new("launchctl")
         .args(["bootout", &target])
         .output()
-        .context("Failed to run launchctl bootout")?;
+        .context(ExitCode::Launchctl.error("Failed to run launchctl bootout"))?;
 
     if output.status.success() {
         println!("Service stopped.");
@@ -191,210 +1204,2696 @@ fn stop_launchagent() -> Result<()> {
         if stderr.contains("No such process") || stderr.contains("Could not find service") {
             println!("Service is not running.");
         } else {
-            anyhow::bail!("Failed to stop service: {}", stderr.trim());
+            let message = format!("Failed to stop service: {}", stderr.trim());
+            return Err(ExitCode::Launchctl.error(message).into());
         }
     }
 
     Ok(())
 }
 
+/// How long `restart` waits for launchd to report the new process.
+const RESTART_CONFIRM_TIMEOUT: Duration = Duration::from_secs(3);
+
 fn restart_launchagent() -> Result<()> {
-    let target = format!("gui/{}/{LABEL}", current_uid());
+    let plist = plist_path()?;
+    let uid = current_uid()?;
 
-    let output = Command::new("launchctl")
-        .args(["kickstart", "-k", &target])
-        .output()
-        .context("Failed to run launchctl kickstart")?;
+    if homebrew_managed()? {
+        brew_services("restart")?;
+    } else if !plist.exists() {
+        return Err(ExitCode::NotInstalled
+            .error("LaunchAgent is not installed. Run `elgato-autolight install` first.")
+            .into());
+    } else if !service_loaded()? {
+        // A stopped agent has been booted out, so load it again instead.
+        let output = Command::new("launchctl")
+            .args(["bootstrap", &format!("gui/{uid}"), &plist.to_string_lossy()])
+            .output()
+            .context(ExitCode::Launchctl.error("Failed to run launchctl bootstrap"))?;
 
-    if output.status.success() {
-        println!("Service restarted.");
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = format!("Failed to load service: {}", stderr.trim());
+            return Err(ExitCode::Launchctl.error(message).into());
+        }
     } else {
+        let output = Command::new("launchctl")
+            .args(["kickstart", "-k", &format!("gui/{uid}/{LABEL}")])
+            .output()
+            .context(ExitCode::Launchctl.error("Failed to run launchctl kickstart"))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = format!("Failed to restart service: {}", stderr.trim());
+            return Err(ExitCode::Launchctl.error(message).into());
+        }
+    }
+
+    let deadline = Instant::now() + RESTART_CONFIRM_TIMEOUT;
+    loop {
+        if let Some(pid) = service_pid()? {
+            println!("Service restarted (PID {pid}).");
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(ExitCode::NotRunning
+                .error("Service was restarted but is not running. Check `elgato-autolight logs`.")
+                .into());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Asks the running monitor to re-read its config file by sending it SIGHUP.
+fn reload_launchagent_config() -> Result<()> {
+    let pid = service_pid()?.context(ExitCode::NotRunning.error("Service is not running"))?;
+
+    let output = Command::new("kill")
+        .args(["-HUP", &pid.to_string()])
+        .output()
+        .context("Failed to run kill")?;
+
+    if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("Failed to restart service: {}", stderr.trim());
+        anyhow::bail!("Failed to signal service: {}", stderr.trim());
     }
 
+    println!("Config reload requested (PID {pid}).");
     Ok(())
 }
 
-fn show_status() -> Result<()> {
-    let config = load_config();
+/// The PID of the running monitor, read from `launchctl list`. `None` when
+/// the agent isn't loaded or is loaded but not running.
+fn service_pid() -> Result<Option<u32>> {
+    let output = Command::new("launchctl")
+        .args(["list", service_label()?])
+        .output()
+        .context(ExitCode::Launchctl.error("Failed to run launchctl list"))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    // The output is an old-style plist with a line like `"PID" = 1234;`.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("\"PID\"")?.trim_start().strip_prefix('=')?;
+        value.trim().trim_end_matches(';').parse().ok()
+    }))
+}
 
-    // Check if loaded via launchctl
+/// Whether launchd currently has the agent loaded.
+fn service_loaded() -> Result<bool> {
     let output = Command::new("launchctl")
-        .args(["list", LABEL])
+        .args(["list", service_label()?])
         .output()
-        .context("Failed to run launchctl list")?;
+        .context(ExitCode::Launchctl.error("Failed to run launchctl list"))?;
+
+    Ok(output.status.success())
+}
+
+/// ANSI styling for `status`, turned off when stdout isn't a terminal or
+/// `NO_COLOR` is set.
+struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    fn detect() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self {
+            enabled: std::io::stdout().is_terminal() && !no_color,
+        }
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn bold(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+
+    fn red(&self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    fn yes_no(&self, value: bool) -> String {
+        if value {
+            self.paint("32", "yes")
+        } else {
+            self.red("no")
+        }
+    }
+}
+
+/// Prints `key: value` rows with the values lined up.
+fn print_rows(indent: &str, rows: &[(&str, String)]) {
+    let width = rows.iter().map(|(key, _)| key.len() + 1).max().unwrap_or(0);
+    for (key, value) in rows {
+        println!("{indent}{:<width$} {value}", format!("{key}:"));
+    }
+}
+
+/// Machine-readable form of `status`, printed by `status --json`. Bump
+/// `STATUS_SCHEMA_VERSION` when removing or renaming fields.
+#[derive(Serialize)]
+struct StatusReport {
+    schema_version: u32,
+    service: &'static str,
+    installed: bool,
+    running: bool,
+    paused: Option<pause::Pause>,
+    elgato_light: Option<ElgatoLightStatus>,
+    /// The state the light was last switched to, as far as the monitor knows.
+    light_state: Option<state::State>,
+    last_command: Option<state::LastCommand>,
+    /// ERROR lines from the latest run in the service's stderr log.
+    recent_errors: Vec<String>,
+    /// The settings in use, without the MQTT password.
+    config: Config,
+    /// Each light with the brightness and temperature it gets right now.
+    lights: Vec<Light>,
+    paths: StatusPaths,
+}
+
+#[derive(Serialize)]
+struct ElgatoLightStatus {
+    path: PathBuf,
+    version: Option<String>,
+}
+
+fn elgato_light_status(config: &Config) -> Option<ElgatoLightStatus> {
+    let found = find_elgato_light(config.elgato_light_path.as_deref()).ok().flatten();
+    found.map(|path| ElgatoLightStatus {
+        version: elgato_light_version(&path).map(|v| v.to_string()),
+        path,
+    })
+}
 
-    let running = output.status.success();
-    let plist = plist_path();
+#[derive(Serialize)]
+struct StatusPaths {
+    config: Option<PathBuf>,
+    plist: PathBuf,
+    logs: PathBuf,
+}
+
+const STATUS_SCHEMA_VERSION: u32 = 1;
+
+/// How many of the latest run's errors `status` shows.
+const RECENT_ERROR_LINES: usize = 5;
+
+fn show_status(json: bool) -> Result<()> {
+    let config = load_config();
+
+    let running = service_loaded()?;
+    let homebrew = homebrew_managed()?;
+    let plist = service_plist()?;
+    let paused = pause::active();
+    let logs = log_dir()?;
+    let recent_errors = logging::recent_errors(&logs.join("stderr.log"), RECENT_ERROR_LINES);
     let installed = plist.exists();
+    let elgato_light = elgato_light_status(&config);
+    let light_state = state::load();
+    let last_command = state::last_command();
 
-    println!("Service:     {LABEL}");
-    println!("Installed:   {}", if installed { "yes" } else { "no" });
-    println!("Running:     {}", if running { "yes" } else { "no" });
-    println!();
-    println!("Config:");
-    println!("  Brightness:   {}%", config.brightness);
-    println!("  Temperature:  {}K", config.temperature);
+    if json {
+        let report = StatusReport {
+            schema_version: STATUS_SCHEMA_VERSION,
+            service: service_label()?,
+            installed,
+            running,
+            paused,
+            elgato_light,
+            light_state,
+            last_command,
+            recent_errors,
+            config: config.redacted(),
+            lights: config.resolved_lights(),
+            paths: StatusPaths {
+                config: config_path(),
+                plist,
+                logs: logs.clone(),
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let style = Style::detect();
+
+    let mut service = vec![
+        ("Service", service_label()?.to_string()),
+        ("Installed", style.yes_no(installed)),
+        ("Running", style.yes_no(running)),
+    ];
+    if homebrew {
+        service.push(("Managed by", "brew services".to_string()));
+    }
+    if let Some(ref pause) = paused {
+        let label = if pause.manual_override { "Override" } else { "Paused" };
+        service.push((label, describe_pause(pause)));
+    }
+    service.push(match elgato_light {
+        Some(ref e) => (
+            "Light CLI",
+            format!(
+                "{} ({})",
+                e.path.display(),
+                e.version.as_deref().unwrap_or("unknown version")
+            ),
+        ),
+        None => ("Light CLI", style.red("elgato-light not found")),
+    });
+    service.push(match light_state {
+        Some(ref saved) => (
+            "Light state",
+            format!("{} ({} ago)", saved.light.action(), format_span(saved.age())),
+        ),
+        None => ("Light state", "unknown".to_string()),
+    });
+    if let Some(ref command) = last_command {
+        let description = describe_last_command(command);
+        let description = if command.failed.is_empty() {
+            description
+        } else {
+            style.red(&description)
+        };
+        service.push(("Last command", description));
+    }
+    print_rows("", &service);
+
+    let mut settings = vec![
+        ("Brightness", format!("{}%", config.brightness)),
+        ("Temperature", format!("{}K", config.temperature)),
+    ];
     if let Some(ref light) = config.light {
-        println!("  Light:        {}", light);
+        settings.push(("Light", light.clone()));
     }
     if let Some(ref ip) = config.ip_address {
-        println!("  IP Address:   {}", ip);
+        settings.push(("IP Address", ip.clone()));
+    }
+    if config.prefer_discovery {
+        settings.push(("Discovery", "preferred over IP address".to_string()));
+    }
+    if config.direct_http {
+        settings.push(("Direct HTTP", "for lights with an IP address".to_string()));
+    }
+    if !config.on_delay().is_zero() {
+        settings.push(("On delay", format_duration(config.on_delay())));
+    }
+    if !config.off_delay().is_zero() {
+        settings.push(("Off delay", format_duration(config.off_delay())));
+    }
+    if config.min_on_duration_ms > 0 {
+        settings.push(("Minimum on", format!("{}ms", config.min_on_duration_ms)));
+    }
+    if config.off_cooldown_ms > 0 {
+        settings.push(("Off cooldown", format!("{}ms", config.off_cooldown_ms)));
+    }
+    if config.min_brightness.is_some() || config.max_brightness.is_some() {
+        let min = config.min_brightness.unwrap_or(Brightness::MIN);
+        let max = config.max_brightness.unwrap_or(Brightness::MAX);
+        settings.push(("Brightness limits", format!("{min}-{max}%")));
+    }
+    if let Some(brightness) = config.standby_brightness.filter(|&b| b > Brightness::MIN) {
+        let temperature = config.standby_temperature.map(|t| format!(", {t}K")).unwrap_or_default();
+        settings.push(("Standby", format!("{brightness}%{temperature}")));
+    }
+    if config.fade_ms > 0 {
+        settings.push(("Fade", format!("{}ms in {} steps", config.fade_ms, config.fade_steps)));
+    }
+    if config.fade_off_ms > 0 {
+        let fade = format!("{}ms in {} steps", config.fade_off_ms, config.fade_steps);
+        settings.push(("Fade off", fade));
+    }
+    if let Some(temperature) = config.idle_temperature {
+        settings.push(("Idle temperature", format!("{temperature}K")));
+    }
+    if config.notify {
+        settings.push(("Notify", style.yes_no(true)));
+    }
+    if config.log_predicate.is_some() {
+        for predicate in config.log_predicates() {
+            settings.push(("Predicate", predicate.to_string()));
+        }
+    }
+    if let (Some(on), Some(off)) = (&config.camera_on_match, &config.camera_off_match) {
+        settings.push(("Camera on match", on.clone()));
+        settings.push(("Camera off match", off.clone()));
+    }
+    if config.react_to_lock {
+        settings.push(("React to lock", style.yes_no(true)));
+    }
+    if let Some(ref predicate) = config.lock_predicate {
+        settings.push(("Lock predicate", predicate.clone()));
+    }
+    if config.reconcile_on_wake {
+        settings.push(("Reconcile on wake", style.yes_no(true)));
+    }
+    if let Some(ref predicate) = config.wake_predicate {
+        settings.push(("Wake predicate", predicate.clone()));
+    }
+    if config.stream_idle_timeout_ms > 0 {
+        settings.push(("Stream idle timeout", format!("{}ms", config.stream_idle_timeout_ms)));
+    }
+    if config.max_stream_failures != default_max_stream_failures() {
+        let limit = match config.max_stream_failures {
+            0 => "never give up".to_string(),
+            max => format!("give up after {max} in a row"),
+        };
+        settings.push(("Stream failures", limit));
+    }
+    if let Some(ref command) = config.on_camera_on {
+        settings.push(("Camera on hook", command.clone()));
+    }
+    if let Some(ref command) = config.on_camera_off {
+        settings.push(("Camera off hook", command.clone()));
+    }
+    if let Some(ref mqtt) = config.mqtt {
+        settings.push(("MQTT", format!("{} on {}", mqtt.topic, mqtt.broker)));
+    }
+    if let Some(ref socket) = config.event_socket {
+        settings.push(("Event socket", socket.display().to_string()));
+    }
+    if !config.app_allowlist.is_empty() {
+        settings.push(("Allowed apps", config.app_allowlist.join(", ")));
+    }
+    if !config.app_blocklist.is_empty() {
+        settings.push(("Blocked apps", config.app_blocklist.join(", ")));
+    }
+    if !config.profiles.is_empty() {
+        let names: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+        settings.push(("Profiles", names.join(", ")));
+    }
+    if !config.camera_allowlist.is_empty() {
+        settings.push(("Allowed cameras", config.camera_allowlist.join(", ")));
+    }
+    if let Some(quiet) = config.quiet_hours {
+        settings.push(("Quiet hours", quiet.to_string()));
     }
     println!();
-    println!("Paths:");
-    println!(
-        "  Config: {}",
-        config_path().map_or("N/A".into(), |p| p.to_string_lossy().to_string())
-    );
-    println!("  Plist:  {}", plist.display());
-    println!("  Logs:   {}", log_dir().display());
+    println!("{}", style.bold("Config:"));
+    print_rows("  ", &settings);
+
+    if !config.schedule.is_empty() {
+        let ranges: Vec<String> =
+            config.schedule.iter().map(|e| format!("{}-{}", e.start, e.end)).collect();
+        let rows: Vec<(&str, String)> = config
+            .schedule
+            .iter()
+            .zip(&ranges)
+            .map(|(entry, range)| {
+                let brightness = entry.brightness.unwrap_or(config.brightness);
+                let temperature = entry.temperature.unwrap_or(config.temperature);
+                (range.as_str(), format!("{brightness}%, {temperature}K"))
+            })
+            .collect();
+        println!();
+        println!("{}", style.bold("Schedule:"));
+        print_rows("  ", &rows);
+    }
+    if !config.lights.is_empty() {
+        let lights = config.resolved_lights();
+        let rows: Vec<(&str, String)> = lights
+            .iter()
+            .map(|light| {
+                let mut value = format!("{}%, {}K", light.brightness, light.temperature);
+                if let (Some(_), Some(ip)) = (&light.name, &light.ip_address) {
+                    value.push_str(&format!(" ({ip})"));
+                }
+                (light.label(), value)
+            })
+            .collect();
+        println!();
+        println!("{}", style.bold("Lights:"));
+        print_rows("  ", &rows);
+    }
+
+    let paths = [
+        (
+            "Config",
+            config_path().map_or("N/A".into(), |p| p.to_string_lossy().to_string()),
+        ),
+        ("Plist", plist.display().to_string()),
+        ("Logs", logs.display().to_string()),
+        ("Log rotation", describe_log_rotation(&config, &logs)),
+    ];
+    println!();
+    println!("{}", style.bold("Paths:"));
+    print_rows("  ", &paths);
+
+    if !recent_errors.is_empty() {
+        println!();
+        println!("{}", style.bold("Recent errors:"));
+        for line in &recent_errors {
+            println!("  {}", style.red(line));
+        }
+    }
 
     Ok(())
 }
 
-// --- Monitor ---
+// --- Logs ---
+
+/// Describes when the service's logs are rotated and how big they are now,
+/// e.g. `at 5.0 MB, keeping 3 old copies (stdout 1.2 MB, stderr 12 KB)`.
+fn describe_log_rotation(config: &Config, dir: &Path) -> String {
+    let kept = match config.max_log_files {
+        0 => "keeping no old copies".to_string(),
+        1 => "keeping 1 old copy".to_string(),
+        n => format!("keeping {n} old copies"),
+    };
+    let sizes: Vec<String> = log_files(dir)
+        .iter()
+        .filter_map(|(name, path)| {
+            let len = std::fs::metadata(path).ok()?.len();
+            Some(format!("{name} {}", format_bytes(len)))
+        })
+        .collect();
+
+    let mut description = format!("at {}, {kept}", format_bytes(config.max_log_bytes));
+    if !sizes.is_empty() {
+        description.push_str(&format!(" ({})", sizes.join(", ")));
+    }
+    description
+}
+
+fn log_files(dir: &Path) -> [(&'static str, PathBuf); 2] {
+    [("stdout", dir.join("stdout.log")), ("stderr", dir.join("stderr.log"))]
+}
+
+/// Prints the last `lines` of each log file, or only the one named `only`,
+/// and with `follow` keeps printing new lines.
+fn show_logs(follow: bool, lines: usize, only: Option<&str>) -> Result<()> {
+    let dir = log_dir()?;
+    let files: Vec<_> = log_files(&dir)
+        .into_iter()
+        .filter(|(name, _)| only.is_none_or(|only| only == *name))
+        .collect();
+
+    if !files.iter().any(|(_, path)| path.exists()) {
+        println!("No logs yet in {}. Logs are written once the service runs.", dir.display());
+        if !follow {
+            return Ok(());
+        }
+    }
+
+    let mut offsets = Vec::new();
+    for (name, path) in &files {
+        let contents = std::fs::read(path).unwrap_or_default();
+        let text = String::from_utf8_lossy(&contents);
+        let all: Vec<&str> = text.lines().collect();
+        for line in &all[all.len().saturating_sub(lines)..] {
+            println!("[{name}] {line}");
+        }
+        offsets.push(contents.len() as u64);
+    }
+
+    if !follow {
+        return Ok(());
+    }
+
+    let mut partial = vec![String::new(); files.len()];
+    loop {
+        std::thread::sleep(Duration::from_millis(500));
+
+        for (i, (name, path)) in files.iter().enumerate() {
+            let Ok(mut file) = std::fs::File::open(path) else {
+                continue;
+            };
+            let len = file.metadata().map_or(0, |m| m.len());
+            if len < offsets[i] {
+                // Truncated or rotated, start over from the top
+                offsets[i] = 0;
+                partial[i].clear();
+            }
+            if len == offsets[i] {
+                continue;
+            }
+
+            file.seek(SeekFrom::Start(offsets[i]))?;
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            offsets[i] += buf.len() as u64;
+
+            partial[i].push_str(&String::from_utf8_lossy(&buf));
+            while let Some(end) = partial[i].find('\n') {
+                println!("[{name}] {}", &partial[i][..end]);
+                partial[i].drain(..=end);
+            }
+        }
+    }
+}
+
+fn rotate_logs(config: &Config) {
+    let Ok(dir) = log_dir() else {
+        return;
+    };
+    for (_, path) in log_files(&dir) {
+        match logging::rotate_if_needed(&path, config.max_log_bytes, config.max_log_files) {
+            Ok(true) => info!("Rotated {}", path.display()),
+            Ok(false) => {}
+            Err(e) => warn!("Failed to rotate {}: {e}", path.display()),
+        }
+    }
+}
+
+// --- Monitor ---
+
+/// How often the monitor checks whether its log files need rotating.
+const LOG_ROTATION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Set by the Ctrl-C handler once the monitor should stop.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Delay before restarting a failed `log stream`, doubled after each failure
+/// up to `MAX_RESTART_DELAY`.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(2);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// A stream that stays up this long counts as healthy and resets the delay.
+const STABLE_STREAM_DURATION: Duration = Duration::from_secs(30);
+
+/// How long to wait for a `log stream` that closed its output to exit, so
+/// its exit status can be reported.
+const STREAM_EXIT_WAIT: Duration = Duration::from_secs(1);
+
+/// How long the config file must go unchanged before a watched edit is
+/// reloaded.
+const CONFIG_WATCH_SETTLE_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CameraState {
+    On,
+    Off,
+}
+
+impl CameraState {
+    fn action(self) -> &'static str {
+        match self {
+            CameraState::On => "on",
+            CameraState::Off => "off",
+        }
+    }
+
+    /// The state the lights follow the camera to.
+    fn light_state(self) -> LightState {
+        match self {
+            CameraState::On => LightState::On,
+            CameraState::Off => LightState::Off,
+        }
+    }
+}
+
+impl std::fmt::Display for CameraState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CameraState::On => "ON",
+            CameraState::Off => "OFF",
+        })
+    }
+}
+
+/// Matches the power state field of a UVCExtension PowerLog entry, e.g.
+/// `"VDCAssistant_Power_State" = On;`.
+static POWER_STATE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#""VDCAssistant_Power_State"\s*=\s*(On|Off)\s*;"#).expect("valid regex")
+});
+
+/// Matches the device field of a PowerLog entry, e.g.
+/// `"VDCAssistant_Device_GUID" = "00000000-1432-0000-1234-000022470000";`.
+static DEVICE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#""VDCAssistant_Device_GUID"\s*=\s*"?([^";\s]+)"?\s*;"#).expect("valid regex")
+});
+
+fn parse_camera_device(line: &str) -> Option<&str> {
+    Some(DEVICE_RE.captures(line)?.get(1)?.as_str())
+}
+
+fn parse_camera_state(line: &str) -> Option<CameraState> {
+    match POWER_STATE_RE.captures(line)?.get(1)?.as_str() {
+        "On" => Some(CameraState::On),
+        "Off" => Some(CameraState::Off),
+        _ => None,
+    }
+}
+
+/// Reads a loginwindow screen lock notification: `Some(true)` when the screen
+/// locks, `Some(false)` when it unlocks.
+fn parse_lock_state(line: &str) -> Option<bool> {
+    if line.contains("com.apple.screenIsUnlocked") {
+        Some(false)
+    } else if line.contains("com.apple.screenIsLocked") {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+struct CameraEvent {
+    state: CameraState,
+    /// The process that logged the event, when it can be determined.
+    app: Option<String>,
+    pid: Option<u32>,
+    /// The camera device's ID, when the entry names one.
+    device: Option<String>,
+}
+
+/// Processes that log camera events on behalf of every app using the
+/// camera, so their name says nothing about which app it is.
+const CAMERA_SERVICES: &[&str] =
+    &["UVCAssistant", "VDCAssistant", "AppleCameraAssistant", "appleh13camerad"];
+
+impl CameraEvent {
+    /// The process that logged the event, as `app[pid]`, for display.
+    fn client(&self) -> String {
+        let app = self.app.as_deref().unwrap_or("unknown");
+        match self.pid {
+            Some(pid) => format!("{app}[{pid}]"),
+            None => app.to_string(),
+        }
+    }
+
+    /// The app the event belongs to, when the line was logged by the app
+    /// itself rather than by a camera service such as `UVCAssistant`.
+    fn session(&self) -> Option<String> {
+        let app = self.app.as_deref()?;
+        (!CAMERA_SERVICES.contains(&app)).then(|| self.client())
+    }
+}
+
+fn parse_camera_event(line: &str, config: &Config) -> Option<CameraEvent> {
+    let state = config.camera_state(line)?;
+    Some(CameraEvent {
+        state,
+        app: parse_process_name(line).map(str::to_string),
+        pid: line.split_whitespace().nth(5).and_then(|pid| pid.parse().ok()),
+        device: parse_camera_device(line).map(str::to_string),
+    })
+}
+
+/// Extracts the process column from a line in `log stream`'s default style:
+///
+/// `<date> <time> <thread> <type> <activity> <pid> <ttl> <process>: ...`
+///
+/// The process may carry a library suffix, as in `zoom.us: (CoreMediaIO)`.
+fn parse_process_name(line: &str) -> Option<&str> {
+    let token = line.split_whitespace().nth(7)?;
+    let name = token.strip_suffix(':')?;
+    (!name.is_empty()).then_some(name)
+}
+
+/// Tracks which clients have the camera open, so one app releasing it
+/// doesn't turn the light off while another is still using it.
+#[derive(Default)]
+struct ActiveClients {
+    /// Apps that logged their own camera events, by `app[pid]`.
+    named: HashSet<String>,
+    /// Sessions from events that don't say which app they belong to, such
+    /// as the default PowerLog ones, counted by their ons and offs.
+    unnamed: usize,
+}
+
+impl ActiveClients {
+    /// Records a client turning the camera on or off. Returns how many
+    /// clients still have it open.
+    fn record(&mut self, state: CameraState, session: Option<String>) -> usize {
+        match (state, session) {
+            (CameraState::On, Some(session)) => {
+                self.named.insert(session);
+            }
+            (CameraState::Off, Some(session)) => {
+                self.named.remove(&session);
+            }
+            (CameraState::On, None) => self.unnamed += 1,
+            (CameraState::Off, None) => self.unnamed = self.unnamed.saturating_sub(1),
+        }
+        self.named.len() + self.unnamed
+    }
+}
+
+/// Holds back camera transitions until the camera has stayed in the new state
+/// for the configured delay, so a quick on/off doesn't flash the light.
+struct Debouncer {
+    on_delay: Duration,
+    off_delay: Duration,
+    /// Shortest time the light stays on before an off can take effect.
+    min_on: Duration,
+    /// Shortest time the light stays off before an on can take effect.
+    off_cooldown: Duration,
+    /// The state the light was last switched to.
+    current: Option<CameraState>,
+    /// When the light was last switched on by this debouncer.
+    on_since: Option<Instant>,
+    /// When the light was last switched off by this debouncer.
+    off_since: Option<Instant>,
+    pending: Option<(CameraState, Instant)>,
+}
+
+impl Debouncer {
+    fn new(
+        on_delay: Duration,
+        off_delay: Duration,
+        min_on: Duration,
+        off_cooldown: Duration,
+    ) -> Self {
+        Self {
+            on_delay,
+            off_delay,
+            min_on,
+            off_cooldown,
+            current: None,
+            on_since: None,
+            off_since: None,
+            pending: None,
+        }
+    }
+
+    /// Records a camera event. Returns the state to switch the light to right
+    /// away, or `None` if the transition is delayed or unnecessary.
+    fn event(&mut self, state: CameraState, now: Instant) -> Option<CameraState> {
+        // An opposite event cancels whatever was waiting, and the light is
+        // left as it is when it's already in this state.
+        if matches!(self.pending, Some((pending, _)) if pending != state) {
+            self.pending = None;
+            if self.current == Some(state) {
+                return None;
+            }
+        }
+        if self.pending.is_some() {
+            return None;
+        }
+
+        let deadline = match state {
+            // An on waits out the off cooldown as well as its own delay.
+            CameraState::On => {
+                let earliest = self.off_since.map_or(now, |since| since + self.off_cooldown);
+                (now + self.on_delay).max(earliest)
+            }
+            // An off waits out the minimum on-time as well as its own delay.
+            CameraState::Off => {
+                let earliest = self.on_since.map_or(now, |since| since + self.min_on);
+                (now + self.off_delay).max(earliest)
+            }
+        };
+
+        if deadline <= now {
+            self.switched(state, now);
+            return Some(state);
+        }
+        if self.current != Some(state) {
+            self.pending = Some((state, deadline));
+        }
+        None
+    }
+
+    fn switched(&mut self, state: CameraState, now: Instant) {
+        // Switching on again while already on keeps the original start time.
+        self.on_since = match state {
+            CameraState::On if self.current == Some(state) => self.on_since.or(Some(now)),
+            CameraState::On => Some(now),
+            CameraState::Off => None,
+        };
+        self.off_since = match state {
+            CameraState::Off if self.current == Some(state) => self.off_since.or(Some(now)),
+            CameraState::Off => Some(now),
+            CameraState::On => None,
+        };
+        self.current = Some(state);
+    }
+
+    /// Returns the pending state once its delay has elapsed.
+    fn poll(&mut self, now: Instant) -> Option<CameraState> {
+        match self.pending {
+            Some((state, deadline)) if now >= deadline => {
+                self.pending = None;
+                self.switched(state, now);
+                Some(state)
+            }
+            _ => None,
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.pending.map(|(_, deadline)| deadline)
+    }
+
+    fn is_pending(&self, state: CameraState) -> bool {
+        matches!(self.pending, Some((pending, _)) if pending == state)
+    }
+}
+
+/// Returns whether any light was switched (always true in a dry run).
+fn switch_lights(
+    binary: &Path,
+    config: &Config,
+    lights: Vec<Light>,
+    state: CameraState,
+    dry_run: bool,
+) -> bool {
+    if dry_run {
+        for line in light_command_lines(binary, &lights, state.light_state()) {
+            info!("[dry-run] would run: {line}");
+        }
+        return true;
+    }
+
+    let labels = light_labels(&lights);
+    let switched = run_light_commands(binary, config, lights, state.light_state());
+    record_command(state.action(), labels, &switched);
+    if switched.is_empty() {
+        return false;
+    }
+
+    if let Err(e) = state::save(state) {
+        warn!("Failed to save light state: {e:#}");
+    }
+    if config.notify {
+        desktop_notify::lights_switched(&switched, state.action());
+    }
+    true
+}
+
+/// Dims `lights` to `standby` in place of turning them off. Saved as off,
+/// since the camera is. Returns whether any light was switched.
+fn dim_to_standby(
+    binary: &Path,
+    config: &Config,
+    lights: Vec<Light>,
+    standby: LightState,
+    dry_run: bool,
+) -> bool {
+    info!("Dimming to standby instead of turning off");
+    if dry_run {
+        for line in light_command_lines(binary, &lights, standby) {
+            info!("[dry-run] would run: {line}");
+        }
+        return true;
+    }
+
+    let labels = light_labels(&lights);
+    let dimmed = run_light_commands(binary, config, lights, standby);
+    record_command("standby", labels, &dimmed);
+    if dimmed.is_empty() {
+        return false;
+    }
+
+    if let Err(e) = state::save(CameraState::Off) {
+        warn!("Failed to save light state: {e:#}");
+    }
+    if config.notify {
+        desktop_notify::lights_switched(&dimmed, "down to standby");
+    }
+    true
+}
+
+fn light_labels(lights: &[Light]) -> Vec<String> {
+    lights.iter().map(|light| light.label().to_string()).collect()
+}
+
+/// Saves which of the lights labelled `labels` weren't among `switched`, for
+/// `status`.
+fn record_command(action: &str, labels: Vec<String>, switched: &[String]) {
+    let failed = labels.into_iter().filter(|label| !switched.contains(label)).collect();
+    if let Err(e) = state::record_command(action, failed) {
+        warn!("Failed to save the light command result: {e:#}");
+    }
+}
+
+/// Longest window searched for a camera event that happened while the
+/// monitor wasn't running.
+const MAX_RECONCILE_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How far back the first run, with no saved state, looks for the camera.
+const FIRST_RUN_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// `start --brightness` and `--temperature`, which win over the schedule,
+/// profiles and per-light values, and are applied again after each reload.
+#[derive(Clone, Copy, Default)]
+struct SettingsOverride {
+    brightness: Option<Brightness>,
+    temperature: Option<Temperature>,
+}
+
+impl SettingsOverride {
+    fn apply(self, config: &mut Config) {
+        if let Some(brightness) = self.brightness {
+            config.brightness = brightness;
+            config.schedule.iter_mut().for_each(|entry| entry.brightness = None);
+            config.lights.iter_mut().for_each(|light| light.brightness = None);
+            config.profiles.values_mut().for_each(|profile| profile.brightness = None);
+        }
+        if let Some(temperature) = self.temperature {
+            config.temperature = temperature;
+            config.schedule.iter_mut().for_each(|entry| entry.temperature = None);
+            config.lights.iter_mut().for_each(|light| light.temperature = None);
+            config.profiles.values_mut().for_each(|profile| profile.temperature = None);
+        }
+    }
+}
+
+/// State carried across camera events for one run of the monitor.
+struct Monitor {
+    binary: PathBuf,
+    config: Config,
+    overrides: SettingsOverride,
+    dry_run: bool,
+    /// Stop once the light has turned on and then off again.
+    once: bool,
+    /// Whether the light was turned on during this run, for `once`.
+    lit: bool,
+    /// Set once a `once` run has done its job.
+    done: bool,
+    debouncer: Debouncer,
+    clients: ActiveClients,
+    /// The app from the latest camera on event, which picks the profile.
+    app: Option<String>,
+    events: Option<EventSocket>,
+    mqtt: Option<MqttPublisher>,
+    hooks: HookRunner,
+    /// Whether the screen is locked, as last reported by the lock stream.
+    locked: bool,
+}
+
+impl Monitor {
+    fn new(binary: PathBuf, config: Config, dry_run: bool, once: bool) -> Self {
+        let debouncer = Debouncer::new(
+            config.on_delay(),
+            config.off_delay(),
+            Duration::from_millis(config.min_on_duration_ms),
+            Duration::from_millis(config.off_cooldown_ms),
+        );
+
+        Self {
+            binary,
+            config,
+            overrides: SettingsOverride::default(),
+            dry_run,
+            once,
+            lit: false,
+            done: false,
+            debouncer,
+            clients: ActiveClients::default(),
+            app: None,
+            events: None,
+            mqtt: None,
+            hooks: HookRunner::start(),
+            locked: false,
+        }
+    }
+
+    fn switch(&self, state: CameraState) -> bool {
+        self.switch_with_standby(state, true)
+    }
+
+    /// Switches the light, dimming it to standby rather than turning it off
+    /// when `standby` allows and `standby_brightness` is set.
+    fn switch_with_standby(&self, state: CameraState, standby: bool) -> bool {
+        if let Some(pause) = pause::active() {
+            let action = state.action();
+            let why = if pause.manual_override { "Manual override" } else { "Automation paused" };
+            info!("{why} {} - not turning light {action}", describe_pause(&pause));
+            return false;
+        }
+        if let Some(quiet) = self.config.quiet_hours_at(TimeOfDay::now()) {
+            if state == CameraState::On {
+                info!("Quiet hours ({quiet}) - not turning light on");
+                return false;
+            }
+        }
+
+        let standby_lights = match state {
+            CameraState::Off if standby => self.config.standby_lights(),
+            _ => None,
+        };
+        let switched = match standby_lights {
+            Some((standby, lights)) => {
+                dim_to_standby(&self.binary, &self.config, lights, standby, self.dry_run)
+            }
+            None => {
+                let app = self.app.as_deref();
+                if let (CameraState::On, Some((name, _))) = (state, self.config.profile(app)) {
+                    info!("Using the {name} profile");
+                }
+                let lights = self.config.lights_for_app(app);
+                switch_lights(&self.binary, &self.config, lights, state, self.dry_run)
+            }
+        };
+
+        if let Some(ref events) = self.events {
+            events.publish(state);
+        }
+        // The retained state follows the light, so a failed switch isn't
+        // published.
+        if let (true, Some(ref mqtt)) = (switched, &self.mqtt) {
+            mqtt.publish(state);
+        }
+        self.run_hook(state, switched);
+        switched
+    }
+
+    fn run_hook(&self, state: CameraState, switched: bool) {
+        let Some(command) = self.config.hook(state) else {
+            return;
+        };
+        if self.dry_run {
+            info!("[dry-run] would run camera {} hook: {command}", state.action());
+            return;
+        }
+
+        let timeout = self.config.hook_timeout_ms;
+        self.hooks.run(Hook {
+            command: command.to_string(),
+            state,
+            switched,
+            timeout: (timeout > 0).then(|| Duration::from_millis(timeout)),
+        });
+    }
+
+    /// Re-reads the config file in place. A broken or invalid file is reported
+    /// and the current settings are kept. Returns whether the log stream
+    /// settings changed, in which case the log streams need restarting.
+    fn reload_config(&mut self) -> bool {
+        match config_path() {
+            Some(path) => self.reload_config_from(&path),
+            None => false,
+        }
+    }
+
+    fn reload_config_from(&mut self, path: &Path) -> bool {
+        let mut config = match read_config(path) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Not reloading config: {e:#}");
+                return false;
+            }
+        };
+
+        let problems = config.validate();
+        if !problems.is_empty() {
+            for problem in problems {
+                warn!("Invalid setting in {}: {problem}", path.display());
+            }
+            warn!("Keeping the current settings.");
+            return false;
+        }
+        self.overrides.apply(&mut config);
+
+        let changes = config_changes(&self.config, &config);
+        if changes.is_empty() {
+            info!("Reloaded config from {}, nothing changed", path.display());
+            return false;
+        }
+
+        if config.event_socket != self.config.event_socket {
+            warn!("event_socket changes take effect after a restart");
+        }
+        if config.mqtt != self.config.mqtt {
+            warn!("mqtt changes take effect after a restart");
+        }
+        if config.watch_config != self.config.watch_config {
+            warn!("watch_config changes take effect after a restart");
+        }
+
+        let streams_changed = config.log_predicates() != self.config.log_predicates()
+            || config.react_to_lock != self.config.react_to_lock
+            || (config.react_to_lock && config.lock_predicate() != self.config.lock_predicate())
+            || config.reconcile_on_wake != self.config.reconcile_on_wake
+            || (config.reconcile_on_wake
+                && config.wake_predicate() != self.config.wake_predicate());
+        self.debouncer.on_delay = config.on_delay();
+        self.debouncer.off_delay = config.off_delay();
+        self.debouncer.min_on = Duration::from_millis(config.min_on_duration_ms);
+        self.debouncer.off_cooldown = Duration::from_millis(config.off_cooldown_ms);
+        self.config = config;
+
+        info!("Reloaded config from {}", path.display());
+        for change in &changes {
+            info!("  {change}");
+        }
+        for light in self.config.resolved_lights() {
+            info!(
+                "Settings for {}: brightness={}%, temperature={}K",
+                light.label(),
+                light.brightness,
+                light.temperature
+            );
+        }
+        if !self.config.react_to_lock {
+            self.locked = false;
+        }
+        streams_changed
+    }
+
+    /// Switches the light for a camera transition seen while monitoring.
+    fn transition(&mut self, state: CameraState) {
+        if !self.switch(state) {
+            return;
+        }
+        match state {
+            CameraState::On => self.lit = true,
+            CameraState::Off => self.done = self.once && self.lit,
+        }
+    }
+
+    /// Turns the light on at startup, which counts as the first half of a
+    /// `once` run.
+    fn switch_on_at_start(&mut self) {
+        if self.switch(CameraState::On) {
+            self.lit = true;
+        }
+    }
+
+    /// Brings the light in line with the camera after a restart. The camera
+    /// state is taken from the most recent event logged since the state was
+    /// saved; if there is none, the camera hasn't changed and the saved state
+    /// still holds.
+    fn reconcile(&mut self, assume_on: bool) {
+        if assume_on {
+            info!("Assuming the camera is on - turning light on");
+            self.debouncer.current = Some(CameraState::On);
+            self.switch_on_at_start();
+            return;
+        }
+
+        let saved = state::load();
+        let window = reconcile_window(saved.as_ref());
+        let Some(saved) = saved else {
+            // macOS can't be asked whether the camera is on, so the system
+            // log's latest camera event stands in for it.
+            if last_camera_event(&self.config, window) == Some(CameraState::On) {
+                info!("Camera is already on - turning light on");
+                self.debouncer.current = Some(CameraState::On);
+                self.switch_on_at_start();
+            }
+            return;
+        };
+
+        let camera = last_camera_event(&self.config, window).unwrap_or(saved.light);
+        self.debouncer.current = Some(camera);
+
+        if camera == saved.light {
+            info!("Light was left {}, camera still {}", saved.light.action(), camera.action());
+        } else {
+            info!(
+                "Light was left {}, but camera is {} - turning light {}",
+                saved.light.action(),
+                camera.action(),
+                camera.action()
+            );
+            match camera {
+                CameraState::On => self.switch_on_at_start(),
+                CameraState::Off => {
+                    self.switch(camera);
+                }
+            }
+        }
+    }
+
+    fn handle_line(&mut self, line: &str) {
+        debug!("[log] {line}");
+
+        if let Some(event) = parse_camera_event(line, &self.config) {
+            debug!("[app] {}", event.app.as_deref().unwrap_or("unknown"));
+            debug!("[camera] {}", event.device.as_deref().unwrap_or("unknown"));
+            self.handle_event(event);
+        }
+    }
+
+    fn handle_event(&mut self, event: CameraEvent) {
+        let state = event.state;
+        let action = state.action();
+
+        if !self.config.camera_allowed(event.device.as_deref()) {
+            let device = event.device.as_deref().unwrap_or("unknown device");
+            info!("Camera {state} from {device} - ignored by camera_allowlist");
+            return;
+        }
+
+        if !self.config.app_allowed(event.app.as_deref()) {
+            let app = event.app.as_deref().unwrap_or("unknown app");
+            info!("Camera {state} from {app} - ignored by app allowlist/blocklist");
+            return;
+        }
+
+        if !self.dry_run {
+            if let Err(e) = history::append(state, event.app.as_deref()) {
+                warn!("Failed to record camera history: {e:#}");
+            }
+        }
+
+        let client = event.client();
+        if state == CameraState::On {
+            self.app = event.app.clone();
+        }
+        let active = self.clients.record(state, event.session());
+        debug!("[clients] {active} active");
+        if state == CameraState::Off && active > 0 {
+            info!("Camera {state} for {client} - still in use by {active} other client(s)");
+            return;
+        }
+
+        let interrupts = self.debouncer.pending.is_some_and(|(pending, _)| pending != state);
+        if let Some(state) = self.debouncer.event(state, Instant::now()) {
+            if self.locked {
+                info!("Camera {state} - screen locked, leaving light off");
+                return;
+            }
+            info!("Camera {state} - turning light {action}");
+            self.transition(state);
+        } else if self.debouncer.is_pending(state) {
+            info!("Camera {state} - waiting before turning light {action}");
+        } else if interrupts {
+            info!("Camera {state} before the delay ran out - light stays {action}");
+        } else {
+            info!("Camera {state} - light already {action}");
+        }
+    }
+
+    /// Applies a debounced transition once it is due.
+    fn poll(&mut self) {
+        if let Some(state) = self.debouncer.poll(Instant::now()) {
+            if self.locked {
+                info!("Camera still {state} - screen locked, leaving light off");
+                return;
+            }
+            info!("Camera still {state} - turning light {}", state.action());
+            self.transition(state);
+        }
+    }
+
+    fn handle_wake_line(&mut self, line: &str) {
+        debug!("[wake] {line}");
+
+        // A dark wake runs maintenance with the display off; the user isn't
+        // back yet.
+        if !line.contains("Wake from") || line.contains("DarkWake") {
+            return;
+        }
+
+        // Camera events logged while going to sleep or asleep were missed,
+        // so look for the latest one since the light was last switched.
+        let logged = state::load()
+            .and_then(|saved| last_camera_event(&self.config, reconcile_window(Some(&saved))));
+        let Some(camera) = logged.or(self.debouncer.current) else {
+            return;
+        };
+
+        self.debouncer.current = Some(camera);
+        self.debouncer.pending = None;
+        if camera == CameraState::Off {
+            self.clients = ActiveClients::default();
+        }
+
+        if self.locked && camera == CameraState::On {
+            info!("Woke from sleep, camera {camera} - screen locked, leaving light off");
+            return;
+        }
+        info!("Woke from sleep, camera {camera} - turning light {}", camera.action());
+        self.switch(camera);
+    }
+
+    fn handle_lock_line(&mut self, line: &str) {
+        debug!("[lock] {line}");
+
+        let Some(locked) = parse_lock_state(line) else {
+            return;
+        };
+        if locked == self.locked {
+            return;
+        }
+        self.locked = locked;
+
+        // The debouncer keeps following the camera while locked, so its
+        // current state is what the light should return to on unlock.
+        let camera_on = self.debouncer.current == Some(CameraState::On);
+        match (locked, camera_on) {
+            (true, true) => {
+                // Nobody is at the desk, so skip standby and turn it off.
+                info!("Screen locked - turning light off");
+                self.switch_with_standby(CameraState::Off, false);
+            }
+            (false, true) => {
+                info!("Screen unlocked, camera still on - turning light on");
+                self.switch(CameraState::On);
+            }
+            (true, false) => info!("Screen locked"),
+            (false, false) => info!("Screen unlocked"),
+        }
+    }
+
+    /// How long the event loop may block before `poll` needs to run.
+    fn wait_timeout(&self) -> Duration {
+        let now = Instant::now();
+        self.debouncer
+            .next_deadline()
+            .map_or(POLL_INTERVAL, |d| d.saturating_duration_since(now))
+            .min(POLL_INTERVAL)
+    }
+}
+
+/// How far back to look for a missed camera event: since the light was last
+/// switched, up to `MAX_RECONCILE_WINDOW`, or `FIRST_RUN_WINDOW` without a
+/// saved state.
+fn reconcile_window(saved: Option<&state::State>) -> Duration {
+    saved.map_or(FIRST_RUN_WINDOW, |saved| saved.age().min(MAX_RECONCILE_WINDOW))
+}
+
+/// Searches the system log for the latest camera event within `window`.
+fn last_camera_event(config: &Config, window: Duration) -> Option<CameraState> {
+    // `log show --last` takes whole minutes; round up so nothing is missed.
+    let minutes = window.as_secs().div_ceil(60).max(1);
+
+    let output = Command::new("log")
+        .args(["show", "--last", &format!("{minutes}m")])
+        .args(["--predicate", &config.combined_log_predicate()])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    latest_camera_state(config, &String::from_utf8_lossy(&output.stdout))
+}
+
+/// The camera state reported by the last camera event in `log`.
+fn latest_camera_state(config: &Config, log: &str) -> Option<CameraState> {
+    log.lines().rev().find_map(|line| config.camera_state(line))
+}
+
+/// Forwards lines from the log stream over a channel so the monitor can wait
+/// on them with a timeout. The channel disconnects when the stream ends.
+fn spawn_line_reader(stdout: std::process::ChildStdout) -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    forward_lines(stdout, tx);
+    rx
+}
+
+/// Sends each line from `stdout` to `tx` on a new thread, until either end
+/// closes.
+fn forward_lines(stdout: std::process::ChildStdout, tx: mpsc::Sender<String>) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    warn!("Error reading log stream: {e}");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+fn require_elgato_light(config: &Config) -> Result<PathBuf> {
+    let found = find_elgato_light(config.elgato_light_path.as_deref());
+    if !matches!(found, Ok(Some(_))) && !config.needs_elgato_light() {
+        // Every light is switched over HTTP, so the binary is never run.
+        return Ok(PathBuf::from("elgato-light"));
+    }
+    let found = found.map_err(|problem| {
+        ExitCode::ElgatoLightMissing
+            .error(format!("{problem}.\nFix or remove elgato_light_path in the config file"))
+    })?;
+    found.ok_or_else(|| {
+        ExitCode::ElgatoLightMissing
+            .error(
+                "elgato-light not found on PATH or in /opt/homebrew/bin or /usr/local/bin.\n\
+                 Install it with: brew install wassimk/tap/elgato-light, \
+                 or set elgato_light_path in the config file",
+            )
+            .into()
+    })
+}
+
+/// Warns about lights that don't answer, or fails when the config requires
+/// them all to be reachable. A light that's off the network now may well be
+/// back before the next camera event.
+fn check_lights_at_start(binary: &Path, config: &Config) -> Result<()> {
+    let mut unreachable = Vec::new();
+    for light in config.resolved_lights() {
+        if let Err(e) = check_light_reachable(binary, &light, config.command_timeout()) {
+            warn!("{} is unreachable: {e:#}", light.label());
+            unreachable.push(light.label().to_string());
+        }
+    }
+
+    if unreachable.is_empty() {
+        return Ok(());
+    }
+    if config.require_light_at_start {
+        let message = format!(
+            "Unreachable at startup: {} (require_light_at_start is set)",
+            unreachable.join(", ")
+        );
+        return Err(ExitCode::LightUnreachable.error(message).into());
+    }
+    warn!("Continuing anyway; the light will be retried on the next camera event.");
+    Ok(())
+}
+
+fn run_monitor(
+    dry_run: bool,
+    once: bool,
+    force: bool,
+    assume_on: bool,
+    overrides: SettingsOverride,
+) -> Result<()> {
+    let mut config = load_config();
+    overrides.apply(&mut config);
+
+    info!("{} {}", logging::RUN_START, env!("CARGO_PKG_VERSION"));
+    // A dry run never touches the light, so it can't conflict with anything.
+    let _lock = if dry_run { None } else { claim_monitor(force)? };
+    // A dry run only prints commands, so it works without elgato-light.
+    let binary = match require_elgato_light(&config) {
+        Ok(binary) => Some(binary),
+        Err(e) if dry_run => {
+            warn!("{e:#}");
+            warn!("Continuing the dry run, printing commands for `elgato-light`");
+            None
+        }
+        Err(e) => return Err(e),
+    };
+
+    if let Some(ref binary) = binary {
+        if !config.needs_elgato_light() {
+            info!("Switching every light over HTTP, without elgato-light");
+        } else {
+            info!("Using elgato-light at: {}", binary.display());
+            match elgato_light_version(binary) {
+                Some(version) if version < MIN_ELGATO_LIGHT_VERSION => warn!(
+                    "elgato-light {version} is older than {MIN_ELGATO_LIGHT_VERSION} and may \
+                     reject some flags; upgrade with: brew upgrade elgato-light"
+                ),
+                Some(version) => info!("elgato-light version: {version}"),
+                None => warn!("Could not determine the elgato-light version"),
+            }
+        }
+        if !dry_run {
+            check_lights_at_start(binary, &config)?;
+        }
+    }
+    let binary = binary.unwrap_or_else(|| PathBuf::from("elgato-light"));
+    for light in config.resolved_lights() {
+        info!(
+            "Settings for {}: brightness={}%, temperature={}K",
+            light.label(),
+            light.brightness,
+            light.temperature
+        );
+    }
+
+    ctrlc::set_handler(|| {
+        SHUTDOWN.store(true, Ordering::SeqCst);
+    })
+    .context("Failed to set signal handler")?;
+
+    // SIGHUP asks for the config file to be re-read without restarting.
+    let reload = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, reload.clone())
+        .context("Failed to set SIGHUP handler")?;
+
+    if config.log_predicate.is_some() {
+        for predicate in config.log_predicates() {
+            info!("Using custom log predicate: {predicate}");
+        }
+    }
+
+    if dry_run {
+        info!("Dry run: light commands will be printed, not run");
+    }
+
+    info!("Monitoring camera events...");
+
+    rotate_logs(&config);
+    let mut next_rotation = Instant::now() + LOG_ROTATION_INTERVAL;
+
+    let mut monitor = Monitor::new(binary, config, dry_run, once);
+    monitor.overrides = overrides;
+    if let Some(ref path) = monitor.config.event_socket {
+        monitor.events = Some(EventSocket::bind(path)?);
+        info!("Publishing events on {}", path.display());
+    }
+    // A dry run leaves the broker alone, so home automation doesn't act on
+    // lights that never switched.
+    match monitor.config.mqtt {
+        Some(ref mqtt) if dry_run => {
+            info!("[dry-run] would publish events to MQTT topic {} on {}", mqtt.topic, mqtt.broker);
+        }
+        Some(ref mqtt) => {
+            monitor.mqtt = Some(MqttPublisher::start(mqtt.clone()));
+            info!("Publishing events to MQTT topic {} on {}", mqtt.topic, mqtt.broker);
+        }
+        None => {}
+    }
+    monitor.reconcile(assume_on);
+    // Retained, so subscribers know the state before the next transition.
+    if let (Some(ref mqtt), Some(state)) = (&monitor.mqtt, monitor.debouncer.current) {
+        mqtt.publish(state);
+    }
+
+    let config_watch = start_config_watch(&monitor.config);
+    let mut config_changed_at: Option<Instant> = None;
+    let mut restart_delay = MIN_RESTART_DELAY;
+    let mut failures = 0;
+
+    while !shutdown_requested() {
+        let started = Instant::now();
+        let mut streams_changed = false;
+        let mut stream_idle = false;
+        // Why the camera stream stopped, when it failed rather than ended.
+        let mut failure: Option<String> = None;
+        let config = &monitor.config;
+        let mut lock_stream = config
+            .react_to_lock
+            .then(|| spawn_side_stream("screen lock", config.lock_predicate()))
+            .flatten();
+        let mut wake_stream = config
+            .reconcile_on_wake
+            .then(|| spawn_side_stream("wake", config.wake_predicate()))
+            .flatten();
+
+        match LogStreams::spawn(&monitor.config.log_predicates()) {
+            Ok(mut streams) => {
+                let mut last_line = Instant::now();
+
+                while !shutdown_requested() && !monitor.done {
+                    // Wake up periodically to notice shutdown, or sooner when a
+                    // debounced transition is due.
+                    match streams.lines.recv_timeout(monitor.wait_timeout()) {
+                        Ok(line) => {
+                            last_line = Instant::now();
+                            monitor.handle_line(&line);
+                        }
+                        Err(RecvTimeoutError::Timeout) if !streams.any_exited() => {}
+                        Err(_) => {
+                            failure = streams
+                                .exit_status()
+                                .filter(|status| !status.success())
+                                .map(|status| format!("'log stream' {status}"));
+                            break;
+                        }
+                    }
+
+                    // `log stream` has been seen to wedge without exiting, so a
+                    // stream that has gone quiet for too long is replaced.
+                    let idle_timeout = monitor.config.stream_idle_timeout_ms;
+                    if idle_timeout > 0
+                        && last_line.elapsed() >= Duration::from_millis(idle_timeout)
+                    {
+                        stream_idle = true;
+                        break;
+                    }
+
+                    monitor.poll();
+
+                    if let Some((_, ref lock_lines)) = lock_stream {
+                        if !drain_lines(lock_lines, |line| monitor.handle_lock_line(line)) {
+                            warn!("Screen lock log stream ended, ignoring lock changes");
+                            lock_stream = None;
+                        }
+                    }
+                    if let Some((_, ref wake_lines)) = wake_stream {
+                        if !drain_lines(wake_lines, |line| monitor.handle_wake_line(line)) {
+                            warn!("Wake log stream ended, ignoring wake events");
+                            wake_stream = None;
+                        }
+                    }
+
+                    // Editors can save in several steps, so wait for the file
+                    // to settle before reading it.
+                    if config_watch.as_ref().is_some_and(|watch| watch.changed()) {
+                        config_changed_at = Some(Instant::now());
+                    }
+                    let settled = config_changed_at
+                        .is_some_and(|at| at.elapsed() >= CONFIG_WATCH_SETTLE_TIME);
+                    if settled {
+                        config_changed_at = None;
+                    }
+
+                    let reload_requested = reload.swap(false, Ordering::SeqCst) || settled;
+                    if reload_requested && monitor.reload_config() {
+                        streams_changed = true;
+                        break;
+                    }
+
+                    if Instant::now() >= next_rotation {
+                        rotate_logs(&monitor.config);
+                        next_rotation = Instant::now() + LOG_ROTATION_INTERVAL;
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to start log stream: {e}");
+                failure = Some(format!("{e:#}"));
+            }
+        }
+
+        drop(lock_stream);
+        drop(wake_stream);
+        // The log stream children have been killed and reaped by now.
+        if monitor.done {
+            info!("Light turned on and off again, exiting.");
+            return Ok(());
+        }
+        if shutdown_requested() {
+            break;
+        }
+        if streams_changed {
+            info!("Log stream settings changed, restarting log streams");
+            continue;
+        }
+        if stream_idle {
+            let idle = Duration::from_millis(monitor.config.stream_idle_timeout_ms);
+            warn!("No log lines for {}, restarting log stream", format_duration(idle));
+            continue;
+        }
+
+        if started.elapsed() >= STABLE_STREAM_DURATION {
+            restart_delay = MIN_RESTART_DELAY;
+            failures = 0;
+        }
+        match failure {
+            Some(ref reason) => {
+                failures += 1;
+                let max = monitor.config.max_stream_failures;
+                if max > 0 && failures >= max {
+                    let message = format!(
+                        "The camera log stream failed {failures} times in a row, last with: \
+                         {reason}.\nCheck that `log stream` runs in a terminal. If it's denied, \
+                         grant the app running elgato-autolight access under System Settings > \
+                         Privacy & Security."
+                    );
+                    return Err(ExitCode::LogStreamFailed.error(message).into());
+                }
+                warn!(
+                    "Log stream failed ({reason}), restarting in {}...",
+                    format_duration(restart_delay)
+                );
+            }
+            None => {
+                failures = 0;
+                warn!("Log stream ended, restarting in {}...", format_duration(restart_delay));
+            }
+        }
+
+        // Sleep in short steps so Ctrl-C isn't held up by a long delay.
+        let resume_at = Instant::now() + restart_delay;
+        while !shutdown_requested() && Instant::now() < resume_at {
+            let remaining = resume_at.saturating_duration_since(Instant::now());
+            std::thread::sleep(POLL_INTERVAL.min(remaining));
+        }
+        restart_delay = (restart_delay * 2).min(MAX_RESTART_DELAY);
+    }
+
+    info!("Shutting down.");
+    Ok(())
+}
+
+/// Refuses to start, unless `force` is set, when another monitor would
+/// switch the light too: the background service, unless this is it, or a
+/// monitor started by hand. Then takes the PID file, which is released when
+/// the returned lock is dropped.
+fn claim_monitor(force: bool) -> Result<Option<InstanceLock>> {
+    let own = std::process::id();
+    let service = service_pid().ok().flatten().filter(|&pid| pid != own);
+    let mut others = Vec::new();
+    if let Some(pid) = service {
+        others.push(format!("the background service (PID {pid})"));
+    }
+    if let Some(pid) = instance::other_monitor().filter(|&pid| Some(pid) != service) {
+        others.push(format!("another monitor (PID {pid})"));
+    }
+
+    if !others.is_empty() {
+        let running = others.join(" and ");
+        if !force {
+            let message = format!(
+                "Already running: {running}. Stop it first, or pass --force to run anyway"
+            );
+            return Err(ExitCode::AlreadyRunning.error(message).into());
+        }
+        warn!("Already running: {running}. Both will switch the light");
+    }
+
+    match instance::acquire() {
+        Ok(lock) => Ok(Some(lock)),
+        Err(e) => {
+            warn!("Other monitors won't see this one: {e:#}");
+            Ok(None)
+        }
+    }
+}
+
+/// Owns a child process and kills and reaps it when dropped, so every exit
+/// path out of the monitor cleans up its `log` process.
+struct ChildGuard(Child);
+
+impl std::ops::Deref for ChildGuard {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for ChildGuard {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.0
+    }
+}
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// The camera `log stream`s, one per predicate, with their lines merged into
+/// one channel. Dropping it kills every stream.
+struct LogStreams {
+    children: Vec<ChildGuard>,
+    lines: Receiver<String>,
+}
+
+impl LogStreams {
+    fn spawn(predicates: &[&str]) -> Result<Self> {
+        let (tx, lines) = mpsc::channel();
+        let mut children = Vec::new();
+        for predicate in predicates {
+            let mut child = spawn_log_stream(predicate)?;
+            forward_lines(child.stdout.take().expect("stdout was piped"), tx.clone());
+            children.push(child);
+        }
+        Ok(LogStreams { children, lines })
+    }
+
+    /// Whether any stream has exited. The channel only disconnects once all
+    /// of them have, so one stream ending is noticed here instead.
+    fn any_exited(&mut self) -> bool {
+        self.children.iter_mut().any(|child| !matches!(child.try_wait(), Ok(None)))
+    }
+
+    /// The exit status of the first stream found to have exited, waiting
+    /// briefly for one that has closed its output but not exited yet.
+    fn exit_status(&mut self) -> Option<ExitStatus> {
+        let deadline = Instant::now() + STREAM_EXIT_WAIT;
+        loop {
+            let exited = self.children.iter_mut().find_map(|child| child.try_wait().ok()?);
+            if exited.is_some() || Instant::now() >= deadline {
+                return exited;
+            }
+            std::thread::sleep(STREAM_EXIT_WAIT / 20);
+        }
+    }
+}
+
+fn spawn_log_stream(predicate: &str) -> Result<ChildGuard> {
+    log_stream_command(predicate)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(ChildGuard)
+        .context("Failed to spawn 'log stream'")
+}
+
+fn log_stream_command(predicate: &str) -> Command {
+    let mut cmd = Command::new("log");
+    cmd.args(["stream", "--predicate", predicate]);
+    cmd
+}
+
+/// Starts watching the config file when `watch_config` is on, or unset and
+/// the monitor is running in a terminal.
+fn start_config_watch(config: &Config) -> Option<ConfigWatch> {
+    if !config.watch_config.unwrap_or_else(|| std::io::stderr().is_terminal()) {
+        return None;
+    }
+    let path = config_path()?;
+    match config_watch::watch(&path) {
+        Ok(watch) => {
+            info!("Watching {} for changes", path.display());
+            Some(watch)
+        }
+        Err(e) => {
+            warn!("Not watching the config file: {e:#}");
+            None
+        }
+    }
+}
+
+/// Starts a `log stream` that runs beside the camera stream, such as the
+/// screen lock stream. If it can't start, its events are just missed.
+fn spawn_side_stream(name: &str, predicate: &str) -> Option<(ChildGuard, Receiver<String>)> {
+    match spawn_log_stream(predicate) {
+        Ok(mut child) => {
+            let lines = spawn_line_reader(child.stdout.take().expect("stdout was piped"));
+            Some((child, lines))
+        }
+        Err(e) => {
+            error!("Failed to start {name} log stream: {e}");
+            None
+        }
+    }
+}
+
+/// Handles every line a side stream has printed so far. Returns false once
+/// the stream has ended.
+fn drain_lines(lines: &Receiver<String>, mut handle: impl FnMut(&str)) -> bool {
+    loop {
+        match lines.try_recv() {
+            Ok(line) => handle(&line),
+            Err(TryRecvError::Empty) => return true,
+            Err(TryRecvError::Disconnected) => return false,
+        }
+    }
+}
+
+// --- Manual control ---
+
+fn run_manual(state: CameraState, names: &[String]) -> Result<()> {
+    let config = load_config();
+    let lights = config.selected_lights(names)?;
+    let binary = require_elgato_light(&config)?;
+
+    println!("Turning light {}...", state.action());
+    if !switch_lights(&binary, &config, lights, state, false) {
+        let message = format!("Failed to turn the light {}", state.action());
+        return Err(ExitCode::LightUnreachable.error(message).into());
+    }
+
+    // Keep the next camera event from undoing what was just asked for.
+    if config.override_grace_ms > 0 {
+        match pause::start_override(Duration::from_millis(config.override_grace_ms)) {
+            Ok(Some(pause)) => {
+                println!("Automation is held off {}.", describe_pause(&pause));
+                println!("Run `elgato-autolight resume` to hand control back sooner.");
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to hold off automation: {e:#}"),
+        }
+    }
+    Ok(())
+}
+
+/// Turns the lights off if any of them is on, and on otherwise. Lights whose
+/// state can't be read are skipped; if none can be, the last state set by
+/// this tool is inverted instead, turning the light on when that's unknown.
+fn run_toggle(names: &[String]) -> Result<()> {
+    let config = load_config();
+    let lights = config.selected_lights(names)?;
+    let binary = require_elgato_light(&config)?;
+
+    let mut known = false;
+    let mut any_on = false;
+    for light in &lights {
+        match light_is_on(&binary, light, config.command_timeout()) {
+            Ok(Some(on)) => {
+                known = true;
+                any_on |= on;
+            }
+            Ok(None) => debug!("elgato-light status doesn't say whether {} is on", light.label()),
+            Err(e) => debug!("Couldn't read the state of {}: {e:#}", light.label()),
+        }
+    }
+    if !known {
+        any_on = state::load().is_some_and(|s| s.light == CameraState::On);
+    }
+
+    let next = if any_on { CameraState::Off } else { CameraState::On };
+    run_manual(next, names)
+}
+
+// --- Pause ---
+
+fn run_pause(duration: Option<Duration>) -> Result<()> {
+    let pause = pause::start(duration)?;
+    println!("Automation paused {}.", describe_pause(&pause));
+    println!("Run `elgato-autolight resume` to turn it back on.");
+    Ok(())
+}
+
+fn run_resume() -> Result<()> {
+    if pause::resume()? {
+        println!("Automation resumed.");
+    } else {
+        println!("Automation was not paused.");
+    }
+    Ok(())
+}
+
+/// Describes when a pause ends, e.g. `until 14:30 (25m left)`.
+fn describe_pause(pause: &pause::Pause) -> String {
+    use chrono::TimeZone;
+
+    let Some(until) = pause.until else {
+        return "until resumed".to_string();
+    };
+    let left = format_span(pause.remaining().unwrap_or_default());
+    match chrono::Local.timestamp_opt(until as i64, 0).single() {
+        Some(time) => format!("until {} ({left} left)", time.format("%H:%M")),
+        None => format!("for {left}"),
+    }
+}
+
+fn describe_last_command(command: &state::LastCommand) -> String {
+    use chrono::TimeZone;
+
+    let outcome = if command.failed.is_empty() {
+        command.action.clone()
+    } else {
+        format!("{} failed for {}", command.action, command.failed.join(", "))
+    };
+    match chrono::Local.timestamp_opt(command.at as i64, 0).single() {
+        Some(time) => format!("{outcome} at {}", time.format("%Y-%m-%d %H:%M:%S")),
+        None => outcome,
+    }
+}
+
+// --- Discovery ---
+
+fn list_lights(timeout: Duration, json: bool, write: bool) -> Result<()> {
+    if !json {
+        println!("Searching for Elgato lights for {}...", format_duration(timeout));
+    }
+    let lights = discover::discover(timeout)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&lights)?);
+        return Ok(());
+    }
+    if lights.is_empty() {
+        println!("No Elgato lights found.");
+        println!(
+            "Check that they're powered and on this network, or search longer with --timeout."
+        );
+        if write {
+            anyhow::bail!("No light found, so the config file was not changed");
+        }
+        return Ok(());
+    }
+
+    let rows: Vec<[&str; 3]> = lights
+        .iter()
+        .map(|light| {
+            [
+                light.name.as_str(),
+                light.ip_address.as_deref().unwrap_or("-"),
+                light.model.as_deref().unwrap_or("-"),
+            ]
+        })
+        .collect();
+    let name_width = rows.iter().map(|r| r[0].len()).max().unwrap_or(0).max(4);
+    let ip_width = rows.iter().map(|r| r[1].len()).max().unwrap_or(0).max(10);
+
+    println!();
+    println!("{:<name_width$}  {:<ip_width$}  MODEL", "NAME", "IP ADDRESS");
+    for [name, ip, model] in rows {
+        println!("{name:<name_width$}  {ip:<ip_width$}  {model}");
+    }
+    println!();
+    if write {
+        return save_discovered_light(&lights);
+    }
+    println!("Use a name with `config set light` or an address with `config set ip_address`.");
+    Ok(())
+}
+
+/// Points the config file's top-level `light` and `ip_address` at the first
+/// of `lights` that has an address.
+fn save_discovered_light(lights: &[discover::DiscoveredLight]) -> Result<()> {
+    let Some(light) = lights.iter().find(|light| light.ip_address.is_some()) else {
+        anyhow::bail!(
+            "None of the lights reported an IP address, so the config file was not changed"
+        );
+    };
+    let path = required_config_path()?;
+    let mut config = read_config(&path)?;
+    if !config.lights.is_empty() {
+        return Err(ExitCode::Config
+            .error(format!(
+                "{} has [[lights]] entries, which --write doesn't change. Add the address to \
+                 one of them by hand.",
+                path.display()
+            ))
+            .into());
+    }
+
+    config.light = Some(light.name.clone());
+    config.ip_address = light.ip_address.clone();
+    save_config(&path, &config)?;
+    println!(
+        "Saved {} ({}) to {}",
+        light.name,
+        light.ip_address.as_deref().unwrap_or_default(),
+        path.display()
+    );
+    if lights.len() > 1 {
+        println!("More than one light was found; run `setup` to pick a different one.");
+    }
+    Ok(())
+}
+
+// --- Setup ---
+
+/// Choices for `setup` given on the command line. Anything left unset is
+/// asked for, or left as it is with `--non-interactive`.
+struct SetupOptions {
+    non_interactive: bool,
+    light: Option<String>,
+    ip_address: Option<String>,
+    brightness: Option<Brightness>,
+    temperature: Option<Temperature>,
+    install: bool,
+    timeout: Duration,
+}
+
+/// Walks through picking a light and its settings, writes them to the config
+/// file and offers to install the LaunchAgent. Settings the wizard doesn't
+/// cover are kept from an existing config file.
+fn run_setup(options: SetupOptions) -> Result<()> {
+    let path = required_config_path()?;
+    let mut config = read_config(&path)?;
+    let interactive = !options.non_interactive;
+    if path.exists() {
+        println!("Updating the existing config at {}", path.display());
+    }
+
+    if options.light.is_some() || options.ip_address.is_some() {
+        config.light = options.light;
+        config.ip_address = options.ip_address;
+    } else if let Some((light, ip_address)) = choose_light(interactive, options.timeout)? {
+        config.light = light;
+        config.ip_address = ip_address;
+    }
+    if !config.lights.is_empty() {
+        warn!("The config has [[lights]] entries, which take the place of the light chosen here");
+    }
+
+    config.brightness = match options.brightness {
+        Some(brightness) => brightness,
+        None if interactive => ask_until_valid("Brightness, 0-100", config.brightness)?,
+        None => config.brightness,
+    };
+    config.temperature = match options.temperature {
+        Some(temperature) => temperature,
+        None if interactive => ask_until_valid("Temperature, 2900-7000K", config.temperature)?,
+        None => config.temperature,
+    };
+
+    let problems = config.validate();
+    if !problems.is_empty() {
+        let message = format!("Invalid settings: {}", problems.join("; "));
+        return Err(ExitCode::Config.error(message).into());
+    }
+    save_config(&path, &config)?;
+    println!("Wrote {}", path.display());
+
+    let install = options.install
+        || (interactive && ask_yes_no("Start the monitor automatically at login?", true)?);
+    if install {
+        install_launchagent(
+            false,
+            ServiceOptions {
+                keep_alive: true,
+                run_at_load: false,
+            },
+        )?;
+    } else if service_loaded().unwrap_or(false) {
+        println!("Run `elgato-autolight reload-config` to apply the new settings.");
+    } else {
+        println!("Run `elgato-autolight install` to start the monitor at login.");
+    }
+    Ok(())
+}
+
+/// Finds lights on the network and picks one: the only one found, or the
+/// user's choice. A light that isn't found can be typed in instead. Returns
+/// the light's name and IP address, or `None` to keep the configured light.
+fn choose_light(
+    interactive: bool,
+    timeout: Duration,
+) -> Result<Option<(Option<String>, Option<String>)>> {
+    println!("Searching for Elgato lights for {}...", format_duration(timeout));
+    let mut lights = discover::discover(timeout)?;
+
+    if !interactive {
+        return match lights.len() {
+            0 => {
+                println!("No Elgato lights found, keeping the configured light.");
+                Ok(None)
+            }
+            1 => Ok(lights.pop().map(|light| (Some(light.name), light.ip_address))),
+            n => {
+                let message = format!("Found {n} lights; pick one with --light or --ip-address");
+                Err(ExitCode::Config.error(message).into())
+            }
+        };
+    }
+
+    if lights.is_empty() {
+        println!("No Elgato lights found.");
+        let answer = ask("Light name or IP address (blank to keep the current one)", "")?;
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        return Ok(Some(if answer.parse::<std::net::IpAddr>().is_ok() {
+            (None, Some(answer))
+        } else {
+            (Some(answer), None)
+        }));
+    }
+
+    for (i, light) in lights.iter().enumerate() {
+        let ip = light.ip_address.as_deref().unwrap_or("no address");
+        let model = light.model.as_deref().map(|m| format!(", {m}")).unwrap_or_default();
+        println!("  {}. {} ({ip}{model})", i + 1, light.name);
+    }
+    loop {
+        let answer = ask("Which light?", "1")?;
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=lights.len()).contains(&n) => {
+                let light = lights.swap_remove(n - 1);
+                return Ok(Some((Some(light.name), light.ip_address)));
+            }
+            _ => println!("Enter a number from 1 to {}.", lights.len()),
+        }
+    }
+}
+
+/// Prints `question` with its default and reads a line from stdin. An empty
+/// answer, or stdin closing, gives the default.
+fn ask(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{question}: ");
+    } else {
+        print!("{question} [{default}]: ");
+    }
+    let answer = read_answer()?;
+    Ok(if answer.is_empty() { default.to_string() } else { answer })
+}
+
+fn ask_yes_no(question: &str, default: bool) -> Result<bool> {
+    loop {
+        print!("{question} [{}]: ", if default { "Y/n" } else { "y/N" });
+        match read_answer()?.to_ascii_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Answer y or n."),
+        }
+    }
+}
+
+fn read_answer() -> Result<String> {
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).context("Failed to read answer")?;
+    Ok(answer.trim().to_string())
+}
+
+/// Asks until the answer parses, re-asking after printing why it didn't.
+fn ask_until_valid<T>(question: &str, default: T) -> Result<T>
+where
+    T: std::str::FromStr<Err = String> + fmt::Display,
+{
+    loop {
+        match ask(question, &default.to_string())?.parse() {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("{e}"),
+        }
+    }
+}
+
+// --- History ---
+
+fn show_history() -> Result<()> {
+    let days = history::summarize(&history::load()?);
+    if days.is_empty() {
+        println!("No camera history recorded yet.");
+        return Ok(());
+    }
+
+    for (day, usage) in &days {
+        println!("{day}  {}", format_span(usage.total));
+
+        let width = usage.apps.keys().map(String::len).max().unwrap_or(0);
+        for (app, span) in &usage.apps {
+            println!("  {app:<width$}  {}", format_span(*span));
+        }
+    }
+    Ok(())
+}
+
+/// Formats a duration to the minute, e.g. `1h 5m`, falling back to seconds
+/// when it is shorter than a minute.
+fn format_span(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes) = (secs / 3600, secs / 60 % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+// --- Test ---
+
+fn run_test(hold: Duration, names: &[String]) -> Result<()> {
+    let config = load_config();
+    let lights = config.selected_lights(names)?;
+    let binary = require_elgato_light(&config)?;
+    println!("Using elgato-light at: {}", binary.display());
+
+    // Both steps run even if the first fails, so the light isn't left on.
+    println!("Turning light on...");
+    let on = switch_lights(&binary, &config, lights.clone(), CameraState::On, false);
+    if on {
+        println!("Holding for {}...", format_duration(hold));
+        std::thread::sleep(hold);
+    }
+
+    println!("Turning light off...");
+    let off = switch_lights(&binary, &config, lights, CameraState::Off, false);
+
+    let failed = match (on, off) {
+        (true, true) => {
+            println!("Done.");
+            return Ok(());
+        }
+        (false, false) => "on and off",
+        (false, true) => "on",
+        (true, false) => "off",
+    };
+    let message = format!("Failed to turn the light {failed}");
+    Err(ExitCode::LightUnreachable.error(message).into())
+}
+
+/// Parses durations like `500ms`, `2s`, `5m`, or `1h`. A bare number is
+/// taken as seconds.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{value}'"))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 60 * 60)),
+        _ => Err(format!("invalid duration unit '{unit}', use ms, s, m, or h")),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.subsec_millis() != 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{}s", duration.as_secs())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    match bytes {
+        b if b >= MB => format!("{:.1} MB", b as f64 / MB as f64),
+        b if b >= KB => format!("{} KB", b / KB),
+        b => format!("{b} B"),
+    }
+}
+
+// --- Build info ---
+
+/// What `--build-info` reports, for pasting into bug reports.
+#[derive(Serialize)]
+struct BuildInfo {
+    version: &'static str,
+    git_sha: &'static str,
+    rustc: &'static str,
+    elgato_light: Option<ElgatoLightStatus>,
+}
+
+fn show_build_info(json: bool) -> Result<()> {
+    let config = load_config();
+    let info = BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("ELGATO_AUTOLIGHT_GIT_SHA"),
+        rustc: env!("ELGATO_AUTOLIGHT_RUSTC_VERSION"),
+        elgato_light: elgato_light_status(&config),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    let elgato_light = match info.elgato_light {
+        Some(ElgatoLightStatus { ref path, version: Some(ref version) }) => {
+            format!("{version} ({})", path.display())
+        }
+        Some(ElgatoLightStatus { ref path, version: None }) => {
+            format!("unknown version ({})", path.display())
+        }
+        None => "not found".to_string(),
+    };
+    print_rows(
+        "",
+        &[
+            ("Version", info.version.to_string()),
+            ("Commit", info.git_sha.to_string()),
+            ("Rust", info.rustc.to_string()),
+            ("elgato-light", elgato_light),
+        ],
+    );
+    Ok(())
+}
+
+// --- Watch ---
+
+/// Prints each camera event as the monitor parses it, with a timestamp. The
+/// light is never touched, so this checks detection on its own.
+fn run_watch() -> Result<()> {
+    let config = load_config();
+
+    ctrlc::set_handler(|| {
+        SHUTDOWN.store(true, Ordering::SeqCst);
+    })
+    .context("Failed to set signal handler")?;
 
-fn find_elgato_light() -> Option<PathBuf> {
-    // Try PATH first
-    if let Ok(output) = Command::new("which").arg("elgato-light").output() {
-        if output.status.success() {
-            let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !path.is_empty() {
-                return Some(PathBuf::from(path));
-            }
+    let mut streams = LogStreams::spawn(&config.log_predicates())?;
+
+    if config.log_predicate.is_some() {
+        for predicate in config.log_predicates() {
+            println!("Using custom log predicate: {predicate}");
         }
     }
+    println!("Watching for camera events. Press Ctrl-C to stop.");
+
+    while !shutdown_requested() {
+        let line = match streams.lines.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => line,
+            Err(RecvTimeoutError::Timeout) if !streams.any_exited() => continue,
+            Err(_) => {
+                // Ctrl-C reaches `log` too, and it can exit before the
+                // handler has set the flag.
+                std::thread::sleep(POLL_INTERVAL);
+                if shutdown_requested() {
+                    break;
+                }
+                anyhow::bail!("log stream ended unexpectedly");
+            }
+        };
 
-    // Fallback locations
-    for path in ["/opt/homebrew/bin/elgato-light", "/usr/local/bin/elgato-light"] {
-        let p = PathBuf::from(path);
-        if p.exists() {
-            return Some(p);
+        if let Some(event) = parse_camera_event(&line, &config) {
+            let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+            let state = event.state.to_string();
+            let device = event.device.as_deref().unwrap_or("unknown camera");
+            println!("{time}  Camera {state:<3}  {}  {device}", event.client());
         }
     }
-
-    None
+    Ok(())
 }
 
-fn run_light_command(binary: &PathBuf, config: &Config, action: &str) {
-    let mut cmd = Command::new(binary);
-    cmd.arg(action);
+// --- Doctor ---
 
-    if action == "on" {
-        cmd.args(["--brightness", &config.brightness.to_string()]);
-        cmd.args(["--temperature", &config.temperature.to_string()]);
-    }
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
 
-    if let Some(ref light) = config.light {
-        cmd.args(["--light", light]);
-    }
-    if let Some(ref ip) = config.ip_address {
-        cmd.args(["--ip-address", ip]);
+fn report_check(status: CheckStatus, message: &str, hint: Option<&str>) {
+    let marker = match status {
+        CheckStatus::Pass => "[ok]  ",
+        CheckStatus::Warn => "[warn]",
+        CheckStatus::Fail => "[FAIL]",
+    };
+    println!("{marker} {message}");
+    if let (Some(hint), false) = (hint, status == CheckStatus::Pass) {
+        println!("       {hint}");
     }
+}
+
+fn run_doctor() -> Result<()> {
+    let mut failures = 0;
+    let mut check = |status: CheckStatus, message: &str, hint: Option<&str>| {
+        if status == CheckStatus::Fail {
+            failures += 1;
+        }
+        report_check(status, message, hint);
+    };
 
-    match cmd.output() {
-        Ok(output) => {
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                eprintln!("elgato-light {action} failed: {}", stderr.trim());
+    // Read quietly; problems with the file are reported by the config check.
+    let quiet_config = config_path().and_then(|path| read_config(&path).ok());
+    let needs_binary = quiet_config.as_ref().is_none_or(Config::needs_elgato_light);
+    let configured_binary = quiet_config.and_then(|config| config.elgato_light_path);
+    let found = find_elgato_light(configured_binary.as_deref());
+    match found {
+        Ok(Some(ref path)) => {
+            check(
+                CheckStatus::Pass,
+                &format!("elgato-light found at {}", path.display()),
+                None,
+            );
+            match elgato_light_version(path) {
+                Some(version) if version < MIN_ELGATO_LIGHT_VERSION => check(
+                    CheckStatus::Warn,
+                    &format!(
+                        "elgato-light {version} is older than {MIN_ELGATO_LIGHT_VERSION}, \
+                         the oldest known to work"
+                    ),
+                    Some("Upgrade it with: brew upgrade elgato-light"),
+                ),
+                Some(version) => check(
+                    CheckStatus::Pass,
+                    &format!("elgato-light version {version}"),
+                    None,
+                ),
+                None => check(
+                    CheckStatus::Warn,
+                    "Could not determine the elgato-light version",
+                    Some("Check that `elgato-light --version` works"),
+                ),
             }
         }
-        Err(e) => eprintln!("Failed to run elgato-light: {e}"),
+        Err(ref problem) => check(
+            if needs_binary { CheckStatus::Fail } else { CheckStatus::Warn },
+            problem,
+            Some("Fix or remove elgato_light_path; PATH isn't searched while it's set."),
+        ),
+        Ok(None) if !needs_binary => check(
+            CheckStatus::Pass,
+            "elgato-light not found, but every light is switched over HTTP",
+            None,
+        ),
+        Ok(None) => check(
+            CheckStatus::Fail,
+            "elgato-light not found on PATH or in /opt/homebrew/bin or /usr/local/bin",
+            Some("Install it with: brew install wassimk/tap/elgato-light"),
+        ),
     }
-}
+    // Lights switched over HTTP can be checked without the binary.
+    let binary =
+        found.ok().flatten().or_else(|| (!needs_binary).then(|| PathBuf::from("elgato-light")));
 
-fn run_monitor(verbose: bool) -> Result<()> {
-    let config = load_config();
+    let mut config = Config::default();
 
-    let binary = find_elgato_light().ok_or_else(|| {
-        anyhow::anyhow!(
-            "elgato-light not found on PATH or in /opt/homebrew/bin or /usr/local/bin.\n\
-             Install it with: brew install wassimk/tap/elgato-light"
-        )
-    })?;
+    if home_dir().is_none() {
+        check(
+            CheckStatus::Fail,
+            "HOME is not set",
+            Some("Config, plist, and log paths are all resolved from HOME."),
+        );
+    } else {
+        let path = required_config_path()?;
+        if !path.exists() {
+            check(
+                CheckStatus::Pass,
+                &format!("No config file at {}, using defaults", path.display()),
+                None,
+            );
+        } else {
+            match read_config(&path) {
+                Ok(parsed) => {
+                    let problems = parsed.validate();
+                    if problems.is_empty() {
+                        check(
+                            CheckStatus::Pass,
+                            &format!("Config {} is valid", path.display()),
+                            None,
+                        );
+                    } else {
+                        check(
+                            CheckStatus::Fail,
+                            &format!("Config has invalid settings: {}", problems.join("; ")),
+                            Some("Those settings use their defaults until they are fixed."),
+                        );
+                    }
+                    if parsed.ignores_top_level_light() {
+                        check(
+                            CheckStatus::Warn,
+                            "Top-level light and ip_address are ignored when [[lights]] is set",
+                            Some("Move them into a [[lights]] entry, or remove them."),
+                        );
+                    }
+                    config = parsed;
+                }
+                Err(e) => check(
+                    CheckStatus::Fail,
+                    &format!("{e:#}"),
+                    Some("Fix the file, or remove it to go back to defaults."),
+                ),
+            }
+        }
 
-    eprintln!("Using elgato-light at: {}", binary.display());
-    eprintln!(
-        "Settings: brightness={}%, temperature={}K",
-        config.brightness, config.temperature
-    );
+        let plist = service_plist()?;
+        if plist.exists() {
+            check(
+                CheckStatus::Pass,
+                &format!("LaunchAgent installed at {}", plist.display()),
+                None,
+            );
+        } else {
+            check(
+                CheckStatus::Warn,
+                "LaunchAgent not installed",
+                Some("Run `elgato-autolight install` to start it automatically on login."),
+            );
+        }
 
-    let shutdown = Arc::new(AtomicBool::new(false));
-    let shutdown_flag = shutdown.clone();
+        match service_loaded() {
+            Ok(true) => check(CheckStatus::Pass, "LaunchAgent loaded", None),
+            Ok(false) => check(
+                CheckStatus::Warn,
+                "LaunchAgent not loaded",
+                Some("Run `elgato-autolight restart`, or `install --force` to reload it."),
+            ),
+            Err(e) => check(CheckStatus::Warn, &format!("{e:#}"), None),
+        }
 
-    ctrlc::set_handler(move || {
-        shutdown_flag.store(true, Ordering::SeqCst);
-    })
-    .context("Failed to set signal handler")?;
+        let logs = log_dir()?;
+        match check_log_dir_writable(&logs) {
+            Ok(()) => check(
+                CheckStatus::Pass,
+                &format!("Log directory {} is writable", logs.display()),
+                None,
+            ),
+            Err(e) => check(
+                CheckStatus::Fail,
+                &format!("{e:#}"),
+                Some("Check the ownership and permissions of ~/Library/Logs."),
+            ),
+        }
+    }
 
-    eprintln!("Monitoring camera events...");
+    match check_log_stream(&config.log_predicates()) {
+        Ok(()) => check(CheckStatus::Pass, "log stream starts and keeps running", None),
+        Err(e) => check(
+            CheckStatus::Fail,
+            &format!("{e:#}"),
+            Some(
+                "The camera monitor relies on the macOS `log` command. Check that `log \
+                 stream` runs in a terminal; if it's denied, grant the app running \
+                 elgato-autolight access under System Settings > Privacy & Security.",
+            ),
+        ),
+    }
 
-    while !shutdown.load(Ordering::SeqCst) {
-        match spawn_log_stream() {
-            Ok(mut child) => {
-                let stdout = child.stdout.take().expect("stdout was piped");
-                let reader = BufReader::new(stdout);
+    if let Some(ref binary) = binary {
+        // Only fatal to the monitor when require_light_at_start is set.
+        let unreachable = if config.require_light_at_start {
+            CheckStatus::Fail
+        } else {
+            CheckStatus::Warn
+        };
+        for light in config.resolved_lights() {
+            match check_light_reachable(binary, &light, config.command_timeout()) {
+                Ok(()) => check(
+                    CheckStatus::Pass,
+                    &format!("{} is reachable", light.label()),
+                    None,
+                ),
+                Err(e) => check(
+                    unreachable,
+                    &format!("{} is unreachable: {e:#}", light.label()),
+                    Some("Check that the light is powered and on the same network."),
+                ),
+            }
+        }
+    }
 
-                for line in reader.lines() {
-                    if shutdown.load(Ordering::SeqCst) {
-                        let _ = child.kill();
-                        break;
-                    }
+    if failures > 0 {
+        let message = format!("{failures} critical check(s) failed");
+        return Err(ExitCode::ChecksFailed.error(message).into());
+    }
+    Ok(())
+}
 
-                    let line = match line {
-                        Ok(l) => l,
-                        Err(e) => {
-                            eprintln!("Error reading log stream: {e}");
-                            break;
-                        }
-                    };
+fn check_log_dir_writable(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create log directory {}", dir.display()))?;
 
-                    if verbose {
-                        eprintln!("[log] {line}");
-                    }
+    let probe = dir.join(".doctor");
+    std::fs::write(&probe, b"")
+        .with_context(|| format!("Log directory {} is not writable", dir.display()))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
 
-                    if line.contains("= On") {
-                        eprintln!("Camera ON - turning light on");
-                        run_light_command(&binary, &config, "on");
-                    } else if line.contains("= Off") {
-                        eprintln!("Camera OFF - turning light off");
-                        run_light_command(&binary, &config, "off");
-                    }
-                }
+/// How long `doctor` lets each `log stream` run before calling it healthy.
+const LOG_STREAM_CHECK_TIME: Duration = Duration::from_secs(2);
 
-                let _ = child.kill();
-                let _ = child.wait();
-            }
-            Err(e) => {
-                eprintln!("Failed to start log stream: {e}");
-            }
-        }
+/// Spawns a `log stream` per predicate, all at once, and confirms each one
+/// is still running after `LOG_STREAM_CHECK_TIME`.
+fn check_log_stream(predicates: &[&str]) -> Result<()> {
+    let mut children = predicates
+        .iter()
+        .map(|predicate| spawn_log_stream(predicate))
+        .collect::<Result<Vec<_>>>()?;
+    std::thread::sleep(LOG_STREAM_CHECK_TIME);
 
-        if !shutdown.load(Ordering::SeqCst) {
-            eprintln!("Log stream ended, restarting in 2s...");
-            std::thread::sleep(std::time::Duration::from_secs(2));
+    for (predicate, child) in predicates.iter().zip(&mut children) {
+        let status = child.try_wait().context("Failed to check 'log stream' status")?;
+        if let Some(status) = status {
+            let within = format_duration(LOG_STREAM_CHECK_TIME);
+            let e = anyhow::anyhow!("'log stream' exited within {within} ({status})");
+            return Err(if predicates.len() > 1 {
+                e.context(format!("Predicate {predicate}"))
+            } else {
+                e
+            });
         }
     }
-
-    eprintln!("Shutting down.");
     Ok(())
 }
 
-fn spawn_log_stream() -> Result<std::process::Child> {
-    Command::new("log")
-        .args([
-            "stream",
-            "--predicate",
-            "subsystem == \"com.apple.UVCExtension\" and composedMessage contains \"Post PowerLog\"",
-        ])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()
-        .context("Failed to spawn 'log stream'")
-}
-
 // --- CLI ---
 
 #[derive(Parser, Debug)]
@@ -404,21 +3903,74 @@ fn spawn_log_stream() -> Result<std::process::Child> {
     version
 )]
 struct Cli {
+    #[arg(
+        long,
+        global = true,
+        env = "ELGATO_AUTOLIGHT_CONFIG",
+        value_name = "PATH",
+        help = "Use this config file instead of ~/.config/elgato-autolight/config.toml"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "ELGATO_AUTOLIGHT_LOG",
+        default_value = "info",
+        value_name = "LEVEL",
+        help = "Log verbosity: off, error, warn, info, debug, or trace"
+    )]
+    log_level: log::LevelFilter,
+
+    #[arg(long, help = "Print the version, commit and elgato-light version for bug reports")]
+    build_info: bool,
+
+    #[arg(long, requires = "build_info", help = "Print --build-info as JSON")]
+    json: bool,
+
     #[command(subcommand)]
-    command: Cmd,
+    command: Option<Cmd>,
 }
 
+const LIGHT_SELECTOR_HELP: &str =
+    "Only switch the configured light with this name or IP address (repeatable)";
+
 #[derive(Subcommand, Debug)]
 enum Cmd {
     /// Run the camera monitor in the foreground
+    ///
+    /// Camera events are read from `log stream` with a predicate matching
+    /// UVC cameras. Advanced users whose camera logs under a different
+    /// subsystem can supply their own predicate with `log_predicate` in the
+    /// config file.
     Start {
-        #[arg(short, long, help = "Print every log stream line received")]
+        #[arg(
+            short,
+            long,
+            help = "Print every log stream line received (same as --log-level debug)"
+        )]
         verbose: bool,
+        #[arg(long, help = "Print the light commands instead of running them")]
+        dry_run: bool,
+        #[arg(long, help = "Exit once the light has turned on and then off again")]
+        once: bool,
+        #[arg(long, help = "Start even if another monitor is already running")]
+        force: bool,
+        #[arg(long, help = "Turn the light on at startup, as if the camera had just turned on")]
+        assume_on: bool,
+        #[arg(long, help = "Brightness, 0-100, instead of the configured ones")]
+        brightness: Option<Brightness>,
+        #[arg(long, help = "Color temperature, 2900-7000K, instead of the configured ones")]
+        temperature: Option<Temperature>,
     },
     /// Install the LaunchAgent for automatic startup
     Install {
         #[arg(short, long, help = "Overwrite existing LaunchAgent")]
         force: bool,
+        #[arg(long, help = "Don't restart the monitor when it exits")]
+        no_keepalive: bool,
+        #[arg(long, help = "Start the monitor as soon as the agent is loaded")]
+        run_at_load: bool,
     },
     /// Uninstall the LaunchAgent
     Uninstall,
@@ -426,21 +3978,960 @@ enum Cmd {
     Stop,
     /// Restart the background service
     Restart,
+    /// Make the background service re-read its config file
+    #[command(visible_alias = "reload")]
+    ReloadConfig,
     /// Show running state, config, and log paths
-    Status,
+    Status {
+        #[arg(long, help = "Print the status as a JSON object")]
+        json: bool,
+    },
+    /// Show the background service's logs
+    Logs {
+        #[arg(short, long, help = "Keep printing new lines as they are written")]
+        follow: bool,
+        #[arg(short = 'n', long, default_value_t = 50, help = "Number of lines to show")]
+        lines: usize,
+        #[arg(long, conflicts_with = "stderr", help = "Only show stdout.log")]
+        stdout: bool,
+        #[arg(long, help = "Only show stderr.log, where the monitor's messages go")]
+        stderr: bool,
+    },
+    /// Turn the light on now, regardless of the camera
+    On {
+        #[arg(long = "light", value_name = "NAME", help = LIGHT_SELECTOR_HELP)]
+        lights: Vec<String>,
+    },
+    /// Turn the light off now, regardless of the camera
+    Off {
+        #[arg(long = "light", value_name = "NAME", help = LIGHT_SELECTOR_HELP)]
+        lights: Vec<String>,
+    },
+    /// Turn the light off if it's on, and on if it's off
+    Toggle {
+        #[arg(long = "light", value_name = "NAME", help = LIGHT_SELECTOR_HELP)]
+        lights: Vec<String>,
+    },
+    /// Show how long the camera was on each day, per app
+    History,
+    /// Stop switching the light automatically, for a while or until resumed
+    Pause {
+        #[arg(value_parser = parse_duration, help = "How long to pause (e.g. 30m, 2h)")]
+        duration: Option<Duration>,
+    },
+    /// Turn automatic switching back on after a pause
+    Resume,
+    /// Find Elgato lights on the local network
+    #[command(visible_alias = "discover")]
+    ListLights {
+        #[arg(long, help = "Print the lights as JSON")]
+        json: bool,
+        #[arg(
+            long,
+            conflicts_with = "json",
+            help = "Save the first light found as light and ip_address in the config file"
+        )]
+        write: bool,
+        #[arg(
+            long,
+            default_value = "3s",
+            value_parser = parse_duration,
+            help = "How long to search (e.g. 500ms, 5s)"
+        )]
+        timeout: Duration,
+    },
+    /// Pick a light and its settings, write the config and install the agent
+    Setup {
+        #[arg(long, help = "Don't ask anything; use the flags and what's found")]
+        non_interactive: bool,
+        #[arg(long, value_name = "NAME", help = "Use the light with this name")]
+        light: Option<String>,
+        #[arg(long, value_name = "IP", help = "Use the light at this IP address")]
+        ip_address: Option<String>,
+        #[arg(long, help = "Brightness, 0-100")]
+        brightness: Option<Brightness>,
+        #[arg(long, help = "Color temperature, 2900-7000K")]
+        temperature: Option<Temperature>,
+        #[arg(long, help = "Install the LaunchAgent without asking")]
+        install: bool,
+        #[arg(
+            long,
+            default_value = "3s",
+            value_parser = parse_duration,
+            help = "How long to search for lights (e.g. 500ms, 5s)"
+        )]
+        timeout: Duration,
+    },
+    /// Turn the light on, wait, then turn it off again
+    Test {
+        #[arg(
+            long,
+            default_value = "2s",
+            value_parser = parse_duration,
+            help = "How long to keep the light on (e.g. 500ms, 2s)"
+        )]
+        hold: Duration,
+        #[arg(long = "light", value_name = "NAME", help = LIGHT_SELECTOR_HELP)]
+        lights: Vec<String>,
+    },
+    /// Print camera on/off events as they are detected, without touching the light
+    Watch,
+    /// Check for common setup problems
+    Doctor,
+    /// View or edit settings in the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCmd,
+    },
+    /// Print a shell completion script
+    Completions { shell: clap_complete::Shell },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCmd {
+    /// Print the value of a setting
+    Get { key: ConfigKey },
+    /// Change a setting, keeping the others intact
+    Set { key: ConfigKey, value: String },
+    /// Print the config file location, and whether it exists
+    Path,
+    /// Print the settings in effect, with defaults filled in
+    Show {
+        #[arg(long, value_enum, default_value_t = ConfigFormat::Toml, help = "Output format")]
+        format: ConfigFormat,
+    },
+    /// Print every setting, for copying to another machine
+    Export {
+        #[arg(long, help = "Print JSON instead of TOML")]
+        json: bool,
+    },
+    /// Replace the config file with settings from a file or stdin
+    Import {
+        #[arg(help = "TOML or JSON file to read; reads stdin when omitted or -")]
+        path: Option<PathBuf>,
+    },
+}
+
+// --- Exit codes ---
+
+/// Process exit codes, so scripts can tell failures apart. Errors without a
+/// code exit with `Failure`; clap exits with 2 for usage errors.
+#[derive(Debug, Clone, Copy)]
+enum ExitCode {
+    Failure = 1,
+    Config = 3,
+    ElgatoLightMissing = 4,
+    NotInstalled = 5,
+    NotRunning = 6,
+    Launchctl = 7,
+    LightUnreachable = 8,
+    ChecksFailed = 9,
+    AlreadyRunning = 10,
+    LogStreamFailed = 11,
+}
+
+impl ExitCode {
+    fn error(self, message: impl Into<String>) -> Coded {
+        Coded {
+            code: self,
+            message: message.into(),
+        }
+    }
+}
+
+/// An error message tagged with the exit code it should produce. Used
+/// directly as an error, or as context on an underlying one.
+#[derive(Debug)]
+struct Coded {
+    code: ExitCode,
+    message: String,
 }
 
+impl fmt::Display for Coded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Coded {}
+
 // --- main ---
 
-fn main() -> Result<()> {
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            // In the service's log, the error that stopped it gets a
+            // timestamp like everything else, and shows up in `status`.
+            if !std::io::stderr().is_terminal() && log::log_enabled!(log::Level::Error) {
+                error!("{e:#}");
+            } else {
+                eprintln!("Error: {e:?}");
+            }
+            let code = e.downcast_ref::<Coded>().map_or(ExitCode::Failure, |c| c.code);
+            std::process::ExitCode::from(code as u8)
+        }
+    }
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
 
-    match cli.command {
-        Cmd::Start { verbose } => run_monitor(verbose),
-        Cmd::Install { force } => install_launchagent(force),
+    let log_level = match cli.command {
+        Some(Cmd::Start { verbose: true, .. }) => cli.log_level.max(log::LevelFilter::Debug),
+        _ => cli.log_level,
+    };
+    logging::init(log_level);
+
+    if let Some(path) = cli.config {
+        // A file asked for by name that isn't there is a mistake, not a reason
+        // to quietly run with defaults. Commands that write the file can
+        // create it.
+        let creates_config = matches!(
+            cli.command,
+            Some(Cmd::Setup { .. })
+                | Some(Cmd::ListLights { write: true, .. })
+                | Some(Cmd::Config {
+                    command: ConfigCmd::Set { .. } | ConfigCmd::Import { .. } | ConfigCmd::Path
+                })
+        );
+        if !path.exists() && !creates_config && !cli.build_info {
+            let message = format!(
+                "Config file {} does not exist. Create it, for example with \
+                 `elgato-autolight --config {} setup`.",
+                path.display(),
+                path.display()
+            );
+            return Err(ExitCode::Config.error(message).into());
+        }
+        let _ = CONFIG_OVERRIDE.set(path);
+    }
+
+    let command = match (cli.command, cli.build_info) {
+        (Some(command), false) => command,
+        (None, true) => return show_build_info(cli.json),
+        (Some(_), true) => Cli::command()
+            .error(ErrorKind::ArgumentConflict, "--build-info can't be used with a subcommand")
+            .exit(),
+        (None, false) => Cli::command()
+            .error(ErrorKind::MissingSubcommand, "a subcommand is required")
+            .exit(),
+    };
+
+    match command {
+        Cmd::Start {
+            dry_run,
+            once,
+            force,
+            assume_on,
+            brightness,
+            temperature,
+            ..
+        } => run_monitor(
+            dry_run,
+            once,
+            force,
+            assume_on,
+            SettingsOverride {
+                brightness,
+                temperature,
+            },
+        ),
+        Cmd::Install { force, no_keepalive, run_at_load } => install_launchagent(
+            force,
+            ServiceOptions {
+                keep_alive: !no_keepalive,
+                run_at_load,
+            },
+        ),
         Cmd::Uninstall => uninstall_launchagent(),
         Cmd::Stop => stop_launchagent(),
         Cmd::Restart => restart_launchagent(),
-        Cmd::Status => show_status(),
+        Cmd::ReloadConfig => reload_launchagent_config(),
+        Cmd::Status { json } => show_status(json),
+        Cmd::Logs {
+            follow,
+            lines,
+            stdout,
+            stderr,
+        } => {
+            let only = match (stdout, stderr) {
+                (true, _) => Some("stdout"),
+                (_, true) => Some("stderr"),
+                _ => None,
+            };
+            show_logs(follow, lines, only)
+        }
+        Cmd::On { lights } => run_manual(CameraState::On, &lights),
+        Cmd::Off { lights } => run_manual(CameraState::Off, &lights),
+        Cmd::Toggle { lights } => run_toggle(&lights),
+        Cmd::History => show_history(),
+        Cmd::Pause { duration } => run_pause(duration),
+        Cmd::Resume => run_resume(),
+        Cmd::ListLights { json, write, timeout } => list_lights(timeout, json, write),
+        Cmd::Setup {
+            non_interactive,
+            light,
+            ip_address,
+            brightness,
+            temperature,
+            install,
+            timeout,
+        } => run_setup(SetupOptions {
+            non_interactive,
+            light,
+            ip_address,
+            brightness,
+            temperature,
+            install,
+            timeout,
+        }),
+        Cmd::Test { hold, lights } => run_test(hold, &lights),
+        Cmd::Watch => run_watch(),
+        Cmd::Doctor => run_doctor(),
+        Cmd::Config { command } => match command {
+            ConfigCmd::Get { key } => config_get(key),
+            ConfigCmd::Set { key, value } => config_set(key, &value),
+            ConfigCmd::Path => config_show_path(),
+            ConfigCmd::Show { format } => config_show(format),
+            ConfigCmd::Export { json } => config_export(json),
+            ConfigCmd::Import { path } => config_import(path.as_deref()),
+        },
+        Cmd::Completions { shell } => {
+            let mut cmd = Cli::command();
+            let name = cmd.get_name().to_string();
+            clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plist(config_path: Option<&Path>, keep_alive: bool, run_at_load: bool) -> String {
+        let plist = generate_plist(
+            "com.example.autolight",
+            "/usr/local/bin/elgato-autolight",
+            config_path,
+            Path::new("/Users/me/Library/Logs/elgato-autolight"),
+            &ServiceOptions { keep_alive, run_at_load },
+        );
+        assert_well_formed(&plist);
+        plist
+    }
+
+    /// Fails unless every `&` starts an entity and every tag is closed in
+    /// order. On macOS, `plutil` checks the file as well.
+    fn assert_well_formed(xml: &str) {
+        const ENTITIES: [&str; 5] = ["&amp;", "&lt;", "&gt;", "&quot;", "&apos;"];
+        for (i, _) in xml.match_indices('&') {
+            assert!(
+                ENTITIES.iter().any(|entity| xml[i..].starts_with(entity)),
+                "raw & in {xml}"
+            );
+        }
+
+        let mut open = Vec::new();
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..].find('>').expect("unclosed <") + start;
+            let tag = &rest[start + 1..end];
+            assert!(!tag.contains('<'), "raw < in {xml}");
+            rest = &rest[end + 1..];
+            if tag.starts_with('?') || tag.starts_with('!') || tag.ends_with('/') {
+                continue;
+            }
+            match tag.strip_prefix('/') {
+                Some(name) => assert_eq!(open.pop(), Some(name), "mismatched </{name}>"),
+                None => open.push(tag.split_whitespace().next().unwrap()),
+            }
+        }
+        assert!(!rest.contains('>'), "raw > in {xml}");
+        assert!(open.is_empty(), "unclosed {open:?}");
+
+        #[cfg(target_os = "macos")]
+        {
+            let path = std::env::temp_dir()
+                .join(format!("elgato-autolight-{}-{:p}.plist", std::process::id(), xml));
+            std::fs::write(&path, xml).unwrap();
+            let lint = Command::new("plutil").arg("-lint").arg(&path).output().unwrap();
+            let _ = std::fs::remove_file(&path);
+            assert!(lint.status.success(), "{}", String::from_utf8_lossy(&lint.stdout));
+        }
+    }
+
+    #[test]
+    fn plist_has_label_program_and_logs() {
+        let plist = plist(None, true, false);
+        assert!(plist.contains("<key>Label</key>\n    <string>com.example.autolight</string>"));
+        assert!(plist.contains(
+            "<array>\n        <string>/usr/local/bin/elgato-autolight</string>\n        \
+             <string>start</string>\n    </array>"
+        ));
+        assert!(plist.contains(
+            "<key>StandardOutPath</key>\n    \
+             <string>/Users/me/Library/Logs/elgato-autolight/stdout.log</string>"
+        ));
+        assert!(plist.contains(
+            "<key>StandardErrorPath</key>\n    \
+             <string>/Users/me/Library/Logs/elgato-autolight/stderr.log</string>"
+        ));
+        assert!(!plist.contains("--config"));
+    }
+
+    #[test]
+    fn plist_passes_config_path() {
+        let plist = plist(Some(Path::new("/tmp/autolight.toml")), true, false);
+        assert!(plist.contains(
+            "<string>/usr/local/bin/elgato-autolight</string>\n        \
+             <string>--config</string>\n        <string>/tmp/autolight.toml</string>\n        \
+             <string>start</string>"
+        ));
+    }
+
+    #[test]
+    fn plist_install_variants() {
+        let default = plist(None, true, false);
+        assert!(default.contains("<key>KeepAlive</key>\n    <true/>"));
+        assert!(!default.contains("RunAtLoad"));
+
+        let no_keepalive = plist(None, false, false);
+        assert!(no_keepalive.contains("<key>KeepAlive</key>\n    <false/>"));
+        assert!(!no_keepalive.contains("RunAtLoad"));
+
+        let run_at_load = plist(None, true, true);
+        assert!(run_at_load.contains("<key>KeepAlive</key>\n    <true/>"));
+        assert!(run_at_load.contains("<key>RunAtLoad</key>\n    <true/>"));
+
+        let both = plist(None, false, true);
+        assert!(both.contains("<key>KeepAlive</key>\n    <false/>"));
+        assert!(both.contains("<key>RunAtLoad</key>\n    <true/>"));
+    }
+
+    #[test]
+    fn plist_escapes_paths() {
+        let binary = r#"/Users/me/Tools & "Apps"/<it's>/elgato-autolight"#;
+        let plist = generate_plist(
+            "com.example.autolight",
+            binary,
+            Some(Path::new("/Users/me/R&D <lights>/config.toml")),
+            Path::new("/Users/me/Logs & 'Traces'"),
+            &ServiceOptions { keep_alive: true, run_at_load: false },
+        );
+        assert_well_formed(&plist);
+        assert!(plist.contains(
+            "<string>/Users/me/Tools &amp; &quot;Apps&quot;/&lt;it&apos;s&gt;/elgato-autolight\
+             </string>"
+        ));
+        assert!(plist.contains("<string>/Users/me/R&amp;D &lt;lights&gt;/config.toml</string>"));
+        let log_dir = "/Users/me/Logs &amp; &apos;Traces&apos;";
+        assert!(plist.contains(&format!("<string>{log_dir}/stdout.log</string>")));
+        assert!(plist.contains(&format!("<string>{log_dir}/stderr.log</string>")));
+        assert_eq!(plist_binary_path(&plist).as_deref(), Some(binary));
+    }
+
+    #[test]
+    fn the_well_formed_check_catches_unescaped_values() {
+        let unescaped = "<plist><string>/Users/me/R&D</string></plist>";
+        assert!(std::panic::catch_unwind(|| assert_well_formed(unescaped)).is_err());
+        let unclosed = "<plist><string>/Users/me</plist>";
+        assert!(std::panic::catch_unwind(|| assert_well_formed(unclosed)).is_err());
+    }
+
+    fn parse_config(contents: &str) -> Config {
+        let table = toml::from_str(contents).expect("test config is valid TOML");
+        config_from_table(table, Path::new("config.toml"))
+    }
+
+    #[test]
+    fn config_accepts_range_limits() {
+        let config = parse_config("brightness = 0\ntemperature = 2900");
+        assert_eq!(config.brightness, Brightness::MIN);
+        assert_eq!(config.temperature, Temperature::MIN);
+
+        let config = parse_config("brightness = 100\ntemperature = 7000");
+        assert_eq!(config.brightness, Brightness::MAX);
+        assert_eq!(config.temperature, Temperature::MAX);
+    }
+
+    #[test]
+    fn config_drops_only_out_of_range_keys() {
+        let config = parse_config("brightness = 101\ntemperature = 4000\nlight = \"Key\"");
+        assert_eq!(config.brightness, default_brightness());
+        assert_eq!(config.temperature, Temperature::try_from(4000).unwrap());
+        assert_eq!(config.light.as_deref(), Some("Key"));
+
+        let config = parse_config("brightness = 30\ntemperature = 2899\nlight = \"Key\"");
+        assert_eq!(config.brightness, Brightness::try_from(30).unwrap());
+        assert_eq!(config.temperature, default_temperature());
+        assert_eq!(config.light.as_deref(), Some("Key"));
+
+        let config = parse_config("brightness = -1\ntemperature = 7001\nip_address = \"10.0.0.2\"");
+        assert_eq!(config.brightness, default_brightness());
+        assert_eq!(config.temperature, default_temperature());
+        assert_eq!(config.ip_address.as_deref(), Some("10.0.0.2"));
+    }
+
+    const MQTT_WITH_PASSWORD: &str = r#"
+        mqtt = { broker = "broker.local", topic = "desk/light", password = "hunter2" }
+    "#;
+
+    #[test]
+    fn config_set_keeps_the_mqtt_password() {
+        let path = std::env::temp_dir()
+            .join(format!("elgato-autolight-{}-set.toml", std::process::id()));
+        std::fs::write(&path, MQTT_WITH_PASSWORD).unwrap();
+
+        config_set_at(&path, ConfigKey::Brightness, "40").unwrap();
+        let config = read_config(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(config.brightness, Brightness::try_from(40).unwrap());
+        assert_eq!(config.mqtt.unwrap().password.as_deref(), Some("hunter2"));
+    }
+
+    #[test]
+    fn shown_config_leaves_out_the_mqtt_password() {
+        let config = parse_config(MQTT_WITH_PASSWORD);
+        let shown = config.redacted();
+        assert!(!toml::to_string(&shown).unwrap().contains("hunter2"));
+        assert!(!serde_json::to_string(&shown).unwrap().contains("hunter2"));
+        assert_eq!(shown.mqtt.unwrap().topic, "desk/light");
+
+        let mut changed = parse_config(MQTT_WITH_PASSWORD);
+        changed.mqtt.as_mut().unwrap().password = Some("swordfish".to_string());
+        changed.brightness = Brightness::try_from(40).unwrap();
+        let changes = config_changes(&config, &changed);
+        assert_eq!(changes, ["brightness: 10 -> 40"]);
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn debouncer_acts_on_the_end_of_a_burst() {
+        let mut debouncer = Debouncer::new(ms(500), ms(500), ms(0), ms(0));
+        let start = Instant::now();
+        let burst = [CameraState::On, CameraState::Off, CameraState::On, CameraState::Off];
+        for (i, state) in burst.into_iter().chain([CameraState::On]).enumerate() {
+            let at = start + ms(100 * i as u64);
+            assert_eq!(debouncer.event(state, at), None);
+            assert_eq!(debouncer.poll(at), None);
+        }
+
+        assert_eq!(debouncer.poll(start + ms(899)), None);
+        assert_eq!(debouncer.poll(start + ms(900)), Some(CameraState::On));
+        assert_eq!(debouncer.poll(start + ms(2000)), None);
+    }
+
+    #[test]
+    fn debouncer_defers_an_on_during_the_off_cooldown() {
+        let mut debouncer = Debouncer::new(ms(0), ms(0), ms(0), ms(5000));
+        let start = Instant::now();
+        assert_eq!(debouncer.event(CameraState::On, start), Some(CameraState::On));
+        assert_eq!(debouncer.event(CameraState::Off, start + ms(1000)), Some(CameraState::Off));
+
+        assert_eq!(debouncer.event(CameraState::On, start + ms(2000)), None);
+        assert!(debouncer.is_pending(CameraState::On));
+        assert_eq!(debouncer.poll(start + ms(5999)), None);
+        assert_eq!(debouncer.poll(start + ms(6000)), Some(CameraState::On));
+    }
+
+    #[test]
+    fn debouncer_ignores_an_on_shorter_than_the_on_delay() {
+        let mut debouncer = Debouncer::new(ms(1000), ms(0), ms(0), ms(0));
+        let start = Instant::now();
+        debouncer.current = Some(CameraState::Off);
+
+        assert_eq!(debouncer.event(CameraState::On, start), None);
+        assert_eq!(debouncer.event(CameraState::Off, start + ms(300)), None);
+        assert!(!debouncer.is_pending(CameraState::On));
+        assert_eq!(debouncer.poll(start + ms(2000)), None);
+    }
+
+    const POWER_LOG_ON: &str = "2024-05-02 09:14:07.118634-0700 0x2f1c3 Default 0x0 201 0 \
+        UVCAssistant: (UVCExtension) [com.apple.UVCExtension:device] Post PowerLog { \
+        \"VDCAssistant_Device_GUID\" = \"00000000-1432-0000-1234-000022470000\"; \
+        \"VDCAssistant_Power_State\" = On; }";
+    const POWER_LOG_OFF: &str = "2024-05-02 09:31:52.004417-0700 0x2f1c3 Default 0x0 201 0 \
+        UVCAssistant: (UVCExtension) [com.apple.UVCExtension:device] Post PowerLog { \
+        \"VDCAssistant_Device_GUID\" = \"00000000-1432-0000-1234-000022470000\"; \
+        \"VDCAssistant_Power_State\" = Off; }";
+
+    #[test]
+    fn power_log_lines_give_the_camera_state() {
+        assert_eq!(parse_camera_state(POWER_LOG_ON), Some(CameraState::On));
+        assert_eq!(parse_camera_state(POWER_LOG_OFF), Some(CameraState::Off));
+        let compact = r#""VDCAssistant_Power_State"=Off;"#;
+        assert_eq!(parse_camera_state(compact), Some(CameraState::Off));
+
+        let event = parse_camera_event(POWER_LOG_ON, &Config::default()).unwrap();
+        assert_eq!(event.app.as_deref(), Some("UVCAssistant"));
+        assert_eq!(event.pid, Some(201));
+        assert_eq!(event.device.as_deref(), Some("00000000-1432-0000-1234-000022470000"));
+    }
+
+    #[test]
+    fn camera_service_events_are_counted_as_sessions() {
+        let config = Config::default();
+        let on = parse_camera_event(POWER_LOG_ON, &config).unwrap();
+        let off = parse_camera_event(POWER_LOG_OFF, &config).unwrap();
+        assert_eq!(on.session(), None);
+
+        let mut clients = ActiveClients::default();
+        assert_eq!(clients.record(on.state, on.session()), 1);
+        assert_eq!(clients.record(on.state, on.session()), 2);
+        assert_eq!(clients.record(off.state, off.session()), 1);
+        assert_eq!(clients.record(off.state, off.session()), 0);
+        assert_eq!(clients.record(off.state, off.session()), 0);
+    }
+
+    #[test]
+    fn app_events_are_tracked_by_app() {
+        let line = |pid, state| {
+            format!(
+                "2024-05-02 09:14:07.1 0x1a Default 0x0 {pid} 0 zoom.us: (CoreMediaIO) \
+                 \"VDCAssistant_Power_State\" = {state};"
+            )
+        };
+        let config = Config::default();
+        let event = |pid, state| parse_camera_event(&line(pid, state), &config).unwrap();
+        assert_eq!(event(812, "On").session().as_deref(), Some("zoom.us[812]"));
+
+        let mut clients = ActiveClients::default();
+        let mut record = |event: CameraEvent| clients.record(event.state, event.session());
+        assert_eq!(record(event(812, "On")), 1);
+        assert_eq!(record(event(813, "On")), 2);
+        assert_eq!(record(event(812, "On")), 2);
+        assert_eq!(record(event(812, "Off")), 1);
+        assert_eq!(record(event(813, "Off")), 0);
+    }
+
+    #[test]
+    fn other_on_off_lines_are_ignored() {
+        let decoys = [
+            "2024-05-02 09:14:07.2 0x1a Default 0x0 88 0 bluetoothd: Power = On;",
+            "2024-05-02 09:14:07.3 0x1b Default 0x0 91 0 WindowServer: Display state = Off",
+            "2024-05-02 09:14:07.4 0x1c Default 0x0 97 0 coreaudiod: \"Mic_Power_State\" = On;",
+            "2024-05-02 09:14:07.5 0x1d Default 0x0 201 0 UVCAssistant: Post PowerLog \
+             { \"VDCAssistant_Power_State\" = Online; }",
+            "2024-05-02 09:14:07.6 0x1e Default 0x0 201 0 UVCAssistant: Power_State On",
+        ];
+        for line in decoys {
+            assert_eq!(parse_camera_state(line), None, "{line}");
+        }
+    }
+
+    fn at(time: &str) -> TimeOfDay {
+        TimeOfDay::try_from(time.to_string()).unwrap()
+    }
+
+    #[test]
+    fn time_range_includes_start_and_excludes_end() {
+        let (start, end) = (at("09:00"), at("17:00"));
+        assert!(time_in_range(at("12:30"), start, end));
+        assert!(time_in_range(at("09:00"), start, end));
+        assert!(!time_in_range(at("17:00"), start, end));
+        assert!(!time_in_range(at("08:59"), start, end));
+        assert!(!time_in_range(at("23:00"), start, end));
+    }
+
+    #[test]
+    fn quiet_hours_wrap_past_midnight() {
+        let config = parse_config("quiet_hours = { start = \"22:00\", end = \"07:00\" }");
+        for inside in ["22:00", "23:59", "00:00", "03:15", "06:59"] {
+            assert!(config.quiet_hours_at(at(inside)).is_some(), "{inside}");
+        }
+        for outside in ["07:00", "12:00", "21:59"] {
+            assert!(config.quiet_hours_at(at(outside)).is_none(), "{outside}");
+        }
+        assert!(Config::default().quiet_hours_at(at("23:00")).is_none());
+    }
+
+    const PROFILES: &str = r#"
+        brightness = 20
+        temperature = 4000
+
+        [profiles."zoom.us"]
+        brightness = 60
+
+        [profiles.FaceTime]
+        brightness = 35
+        temperature = 5500
+    "#;
+
+    fn settings(lights: &[Light]) -> (u8, u16) {
+        (lights[0].brightness.get(), lights[0].temperature.into())
+    }
+
+    #[test]
+    fn profile_matches_app_ignoring_case() {
+        let config = parse_config(PROFILES);
+        assert_eq!(config.profile(Some("zoom.us")).map(|(name, _)| name), Some("zoom.us"));
+        assert_eq!(config.profile(Some("facetime")).map(|(name, _)| name), Some("FaceTime"));
+        assert!(config.profile(Some("Safari")).is_none());
+        assert!(config.profile(None).is_none());
+    }
+
+    #[test]
+    fn lights_for_app_use_the_profile_over_top_level_settings() {
+        let config = parse_config(PROFILES);
+        assert_eq!(settings(&config.lights_for_app(Some("zoom.us"))), (60, 4000));
+        assert_eq!(settings(&config.lights_for_app(Some("FACETIME"))), (35, 5500));
+        assert_eq!(settings(&config.lights_for_app(Some("Safari"))), (20, 4000));
+        assert_eq!(settings(&config.lights_for_app(None)), (20, 4000));
+    }
+
+    #[test]
+    fn latest_camera_event_wins() {
+        let log = [POWER_LOG_ON, "unrelated line", POWER_LOG_OFF, "another unrelated line"];
+        let config = Config::default();
+        assert_eq!(latest_camera_state(&config, &log.join("\n")), Some(CameraState::Off));
+        assert_eq!(latest_camera_state(&config, &log[..2].join("\n")), Some(CameraState::On));
+        assert_eq!(latest_camera_state(&config, "unrelated line"), None);
+    }
+
+    #[test]
+    fn custom_match_strings_pick_the_camera_state() {
+        let config = parse_config("camera_on_match = \"= Online\"\ncamera_off_match = \"= On\"");
+        let log = "Camera state = On\nCamera state = Online";
+        assert_eq!(latest_camera_state(&config, log), Some(CameraState::On));
+        assert_eq!(latest_camera_state(&config, "Camera state = On"), Some(CameraState::Off));
+    }
+
+    #[test]
+    fn configured_predicates_replace_the_default() {
+        assert_eq!(Config::default().combined_log_predicate(), DEFAULT_LOG_PREDICATE);
+        let one = parse_config("log_predicate = 'subsystem == \"com.example.cam\"'");
+        assert_eq!(one.combined_log_predicate(), "subsystem == \"com.example.cam\"");
+        let many = parse_config("log_predicate = ['process == \"a\"', 'process == \"b\"']");
+        assert_eq!(many.combined_log_predicate(), "(process == \"a\") or (process == \"b\")");
+    }
+
+    #[test]
+    fn the_monitor_streams_each_configured_predicate() {
+        let stream_args = |config: &Config| -> Vec<Vec<String>> {
+            let args = |cmd: Command| cmd.get_args().map(|a| a.to_str().unwrap().into()).collect();
+            config.log_predicates().into_iter().map(|p| args(log_stream_command(p))).collect()
+        };
+        let args =
+            |predicate: &str| ["stream", "--predicate", predicate].map(String::from).to_vec();
+
+        assert_eq!(stream_args(&Config::default()), [args(DEFAULT_LOG_PREDICATE)]);
+        let one = parse_config("log_predicate = 'subsystem == \"com.example.cam\"'");
+        assert_eq!(stream_args(&one), [args("subsystem == \"com.example.cam\"")]);
+        let many = parse_config("log_predicate = ['process == \"a\"', 'process == \"b\"']");
+        assert_eq!(stream_args(&many), [args("process == \"a\""), args("process == \"b\"")]);
+        let empty = parse_config("log_predicate = []");
+        assert_eq!(stream_args(&empty), [args(DEFAULT_LOG_PREDICATE)]);
+    }
+
+    #[test]
+    fn reconcile_window_covers_the_time_since_the_light_switched() {
+        let saved_at = |ago: u64| state::State {
+            light: CameraState::On,
+            updated_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                - ago,
+        };
+
+        assert_eq!(reconcile_window(None), FIRST_RUN_WINDOW);
+        let window = reconcile_window(Some(&saved_at(600)));
+        assert!((Duration::from_secs(600)..Duration::from_secs(610)).contains(&window));
+        assert_eq!(reconcile_window(Some(&saved_at(3 * 24 * 60 * 60))), MAX_RECONCILE_WINDOW);
+    }
+
+    #[test]
+    fn reload_swaps_in_a_valid_config_and_keeps_the_old_one_otherwise() {
+        let _home = lock_home();
+        let path = std::env::temp_dir()
+            .join(format!("elgato-autolight-{}-reload.toml", std::process::id()));
+        let mut monitor =
+            Monitor::new(PathBuf::from("elgato-light"), parse_config(""), true, false);
+        let brightness = |monitor: &Monitor| monitor.config.lights_for_app(None)[0].brightness;
+
+        std::fs::write(&path, "brightness = 30\noff_cooldown_ms = 2000").unwrap();
+        assert!(!monitor.reload_config_from(&path));
+        assert_eq!(brightness(&monitor), Brightness::try_from(30).unwrap());
+        assert_eq!(monitor.debouncer.off_cooldown, Duration::from_millis(2000));
+
+        for broken in [
+            "brightness = 101",
+            "brightness = ",
+            "brightness = 40\nquiet_hours = { start = \"22:00\", end = \"22:00\" }",
+        ] {
+            std::fs::write(&path, broken).unwrap();
+            assert!(!monitor.reload_config_from(&path));
+            assert_eq!(brightness(&monitor), Brightness::try_from(30).unwrap(), "{broken}");
+        }
+
+        monitor.overrides.brightness = Some(Brightness::try_from(70).unwrap());
+        std::fs::write(&path, "brightness = 40").unwrap();
+        monitor.reload_config_from(&path);
+        assert_eq!(brightness(&monitor), Brightness::try_from(70).unwrap());
+
+        std::fs::write(&path, "log_predicate = 'process == \"camera\"'").unwrap();
+        assert!(monitor.reload_config_from(&path));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn once_is_done_after_an_on_then_off() {
+        let _home = lock_home();
+        let monitor =
+            |once| Monitor::new(PathBuf::from("elgato-light"), parse_config(""), true, once);
+
+        let mut run = monitor(true);
+        run.transition(CameraState::Off);
+        assert!(!run.done);
+        run.transition(CameraState::On);
+        assert!(!run.done);
+        run.transition(CameraState::Off);
+        assert!(run.done);
+
+        let mut run = monitor(false);
+        run.transition(CameraState::On);
+        run.transition(CameraState::Off);
+        assert!(!run.done);
+    }
+
+    #[test]
+    fn light_stays_on_until_the_last_overlapping_session_ends() {
+        let _home = lock_home();
+        let config = parse_config("debounce_ms = 0");
+        let event = |line| parse_camera_event(line, &config).unwrap();
+        let binary = PathBuf::from("elgato-light");
+        let mut monitor = Monitor::new(binary, parse_config("debounce_ms = 0"), true, false);
+
+        monitor.handle_event(event(POWER_LOG_ON));
+        monitor.handle_event(event(POWER_LOG_ON));
+        assert_eq!(monitor.debouncer.current, Some(CameraState::On));
+        monitor.handle_event(event(POWER_LOG_OFF));
+        assert_eq!(monitor.debouncer.current, Some(CameraState::On));
+        monitor.handle_event(event(POWER_LOG_OFF));
+        assert_eq!(monitor.debouncer.current, Some(CameraState::Off));
+    }
+
+    const TWO_LIGHTS: &str = r#"
+        brightness = 20
+
+        [[lights]]
+        light = "Key"
+        brightness = 50
+
+        [[lights]]
+        ip_address = "192.168.1.21"
+        temperature = 3500
+    "#;
+
+    #[test]
+    fn each_light_entry_gets_its_own_command() {
+        let config = parse_config(TWO_LIGHTS);
+        let lines = light_command_lines(
+            Path::new("elgato-light"),
+            &config.resolved_lights(),
+            LightState::On,
+        );
+        assert_eq!(
+            lines,
+            [
+                "elgato-light on --brightness 50 --temperature 5000 --light Key",
+                "elgato-light on --brightness 20 --temperature 3500 --ip-address 192.168.1.21",
+            ]
+        );
+    }
+
+    #[test]
+    fn top_level_light_is_flagged_when_lights_are_set() {
+        assert!(!parse_config(TWO_LIGHTS).ignores_top_level_light());
+        assert!(!parse_config("light = \"Key\"").ignores_top_level_light());
+        let both = format!("light = \"Key\"\n{TWO_LIGHTS}");
+        assert!(parse_config(&both).ignores_top_level_light());
+        let both = format!("ip_address = \"192.168.1.20\"\n{TWO_LIGHTS}");
+        assert!(parse_config(&both).ignores_top_level_light());
+    }
+
+    #[test]
+    fn child_guard_kills_and_reaps_on_drop() {
+        let child = Command::new("sleep").arg("60").spawn().unwrap();
+        let pid = child.id().to_string();
+        let alive = || {
+            let status = Command::new("kill").args(["-0", &pid]).stderr(Stdio::null()).status();
+            status.unwrap().success()
+        };
+        let guard = ChildGuard(child);
+        assert!(alive());
+
+        drop(guard);
+        // `kill -0` still finds a zombie, so this fails unless it was reaped.
+        assert!(!alive());
+    }
+
+    /// Held by every test that changes HOME or reads it, directly or through
+    /// a monitor that checks the pause file, so no test sees another's HOME.
+    fn lock_home() -> std::sync::MutexGuard<'static, ()> {
+        static HOME_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        HOME_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs `f` with HOME unset and then empty, restoring HOME before
+    /// returning the results.
+    fn without_home<T>(f: impl Fn() -> T) -> Vec<T> {
+        let _lock = lock_home();
+        let home = std::env::var_os("HOME");
+        let results = [None, Some("")]
+            .into_iter()
+            .map(|unset| {
+                match unset {
+                    None => std::env::remove_var("HOME"),
+                    Some(empty) => std::env::set_var("HOME", empty),
+                }
+                f()
+            })
+            .collect();
+        match home {
+            Some(home) => std::env::set_var("HOME", home),
+            None => std::env::remove_var("HOME"),
+        }
+        results
+    }
+
+    #[test]
+    fn unset_home_is_an_error_not_a_panic() {
+        let results = without_home(|| {
+            let options = ServiceOptions { keep_alive: true, run_at_load: false };
+            [
+                plist_path().map(drop),
+                log_dir().map(drop),
+                service_plist().map(drop),
+                required_config_path().map(drop),
+                install_launchagent(false, options),
+                uninstall_launchagent(),
+                state::save(CameraState::On),
+                history::append(CameraState::On, None),
+                pause::resume().map(drop),
+                instance::acquire().map(drop),
+            ]
+            .map(|result| result.map_err(|e| format!("{e:#}")))
+        });
+        for result in results.into_iter().flatten() {
+            assert!(result.as_ref().is_err_and(|e| e.starts_with("HOME not set")), "{result:?}");
+        }
+    }
+
+    #[test]
+    fn unset_home_skips_recording_the_last_command() {
+        let results = without_home(|| {
+            (config_path(), state::record_command("on", Vec::new()).is_ok(), state::load())
+        });
+        for (path, recorded, saved) in results {
+            assert!(path.is_none());
+            assert!(recorded);
+            assert!(saved.is_none());
+        }
     }
 }
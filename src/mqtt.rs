@@ -0,0 +1,234 @@
+//! Publishes light transitions to an MQTT broker for home automation.
+//!
+//! Only the small part of MQTT 3.1.1 needed to publish is spoken: connect,
+//! publish at QoS 0 and keep-alive pings. Each transition is published as
+//! `on` or `off`, retained so new subscribers see the current state.
+//!
+//! The connection lives on a background thread, so a slow or unreachable
+//! broker never holds up the monitor. While the broker is down only the
+//! latest state is kept, and it is sent once a reconnect succeeds.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use crate::CameraState;
+
+const DEFAULT_PORT: u16 = 1883;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Sent to the broker in CONNECT; pings go out at half this.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// The `mqtt` table in the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MqttConfig {
+    /// `host` or `host:port`, optionally prefixed with `mqtt://`.
+    pub broker: String,
+    pub topic: String,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    /// Left out of `config show`, `config export`, `status --json` and
+    /// reload logs by `Config::redacted`.
+    pub password: Option<String>,
+}
+
+fn default_client_id() -> String {
+    "elgato-autolight".to_string()
+}
+
+impl MqttConfig {
+    fn address(&self) -> (String, u16) {
+        let broker = self.broker.strip_prefix("mqtt://").unwrap_or(&self.broker);
+        match broker.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host.to_string(), port),
+                Err(_) => (broker.to_string(), DEFAULT_PORT),
+            },
+            None => (broker.to_string(), DEFAULT_PORT),
+        }
+    }
+}
+
+/// Hands states to the publishing thread. Dropping it disconnects.
+pub struct MqttPublisher {
+    states: Sender<CameraState>,
+}
+
+impl MqttPublisher {
+    pub fn start(config: MqttConfig) -> Self {
+        let (states, queue) = mpsc::channel();
+        std::thread::spawn(move || run(&config, &queue));
+        Self { states }
+    }
+
+    pub fn publish(&self, state: CameraState) {
+        let _ = self.states.send(state);
+    }
+}
+
+fn run(config: &MqttConfig, queue: &Receiver<CameraState>) {
+    let mut connection: Option<TcpStream> = None;
+    let mut pending: Option<CameraState> = None;
+    let mut reconnect_at = Instant::now();
+    let mut reconnect_delay = MIN_RECONNECT_DELAY;
+    // Only the first failure of an outage is a warning.
+    let mut failing = false;
+
+    loop {
+        let wait = match (&connection, pending) {
+            (None, Some(_)) => reconnect_at.saturating_duration_since(Instant::now()),
+            _ => KEEP_ALIVE / 2,
+        };
+        match queue.recv_timeout(wait) {
+            Ok(state) => pending = Some(queue.try_iter().last().unwrap_or(state)),
+            Err(RecvTimeoutError::Timeout) => {
+                if pending.is_none() {
+                    if let Some(ref mut stream) = connection {
+                        if let Err(e) = ping(stream) {
+                            log::debug!("MQTT broker stopped answering: {e:#}");
+                            connection = None;
+                        }
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Some(ref mut stream) = connection {
+                    let _ = stream.write_all(&[0xE0, 0x00]);
+                }
+                return;
+            }
+        }
+
+        let Some(state) = pending else {
+            continue;
+        };
+
+        if connection.is_none() {
+            if Instant::now() < reconnect_at {
+                continue;
+            }
+            match connect(config) {
+                Ok(stream) => {
+                    log::info!("Connected to MQTT broker {}", config.broker);
+                    connection = Some(stream);
+                    reconnect_delay = MIN_RECONNECT_DELAY;
+                    failing = false;
+                }
+                Err(e) => {
+                    if failing {
+                        log::debug!("MQTT broker {} still unreachable: {e:#}", config.broker);
+                    } else {
+                        log::warn!("Can't reach MQTT broker {}, will retry: {e:#}", config.broker);
+                        failing = true;
+                    }
+                    reconnect_at = Instant::now() + reconnect_delay;
+                    reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(ref mut stream) = connection {
+            match publish(stream, &config.topic, state.action()) {
+                Ok(()) => pending = None,
+                Err(e) => {
+                    log::warn!("Lost connection to MQTT broker {}: {e:#}", config.broker);
+                    connection = None;
+                    reconnect_at = Instant::now();
+                }
+            }
+        }
+    }
+}
+
+fn connect(config: &MqttConfig) -> Result<TcpStream> {
+    let (host, port) = config.address();
+    let address = (host.as_str(), port)
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {host}"))?
+        .next()
+        .with_context(|| format!("No address for {host}"))?;
+
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {address}"))?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+
+    let mut flags = 0x02; // clean session
+    let mut payload = encode_string(&config.client_id);
+    if let Some(ref username) = config.username {
+        flags |= 0x80;
+        payload.extend(encode_string(username));
+    }
+    if let Some(ref password) = config.password {
+        flags |= 0x40;
+        payload.extend(encode_string(password));
+    }
+
+    let mut body = encode_string("MQTT");
+    body.push(4); // protocol level 3.1.1
+    body.push(flags);
+    body.extend((KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    body.extend(payload);
+    stream.write_all(&packet(0x10, &body))?;
+
+    let mut connack = [0; 4];
+    stream.read_exact(&mut connack).context("No CONNACK from broker")?;
+    match connack {
+        [0x20, 0x02, _, 0] => Ok(stream),
+        [0x20, 0x02, _, 4 | 5] => bail!("Broker rejected the username or password"),
+        [0x20, 0x02, _, code] => bail!("Broker refused the connection (code {code})"),
+        _ => bail!("Unexpected reply from broker"),
+    }
+}
+
+/// Publishes `payload` to `topic` at QoS 0 with the retain flag set.
+fn publish(stream: &mut TcpStream, topic: &str, payload: &str) -> Result<()> {
+    let mut body = encode_string(topic);
+    body.extend(payload.as_bytes());
+    stream.write_all(&packet(0x31, &body))?;
+    Ok(())
+}
+
+/// Sends PINGREQ and waits for PINGRESP, which also keeps the broker's
+/// replies from piling up unread.
+fn ping(stream: &mut TcpStream) -> Result<()> {
+    stream.write_all(&[0xC0, 0x00])?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [0xD0, 0x00] {
+        bail!("Unexpected reply to ping");
+    }
+    Ok(())
+}
+
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    // Remaining length: 7 bits per byte, high bit set when more follow.
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend(body);
+    packet
+}
+
+fn encode_string(value: &str) -> Vec<u8> {
+    let mut encoded = (value.len() as u16).to_be_bytes().to_vec();
+    encoded.extend(value.as_bytes());
+    encoded
+}
@@ -0,0 +1,95 @@
+//! A marker that pauses the monitor's automation, written by `pause` or by a
+//! manual light command and removed by `resume` or once it expires.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Pause {
+    /// Seconds since the Unix epoch when the pause ends. `None` pauses until
+    /// `resume`.
+    #[serde(default)]
+    pub until: Option<u64>,
+    /// Set for the grace period after a manual `on`, `off` or `toggle`.
+    #[serde(default)]
+    pub manual_override: bool,
+}
+
+impl Pause {
+    /// Time left before the pause ends, or `None` if it has no end.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.until.map(|until| Duration::from_secs(until.saturating_sub(now())))
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    crate::home_dir().map(|home| home.join(".config/elgato-autolight/pause.json"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The pause in effect, if any. An expired or unreadable marker counts as
+/// not paused.
+pub fn active() -> Option<Pause> {
+    let contents = std::fs::read_to_string(path()?).ok()?;
+    let pause: Pause = serde_json::from_str(&contents).ok()?;
+    match pause.until {
+        Some(until) if until <= now() => None,
+        _ => Some(pause),
+    }
+}
+
+/// Pauses automation for `duration`, or until resumed when it is `None`.
+pub fn start(duration: Option<Duration>) -> Result<Pause> {
+    let pause = Pause {
+        until: duration.map(|d| now() + d.as_secs().max(1)),
+        manual_override: false,
+    };
+    write(&pause)?;
+    Ok(pause)
+}
+
+/// Holds off automation for `grace` after the light was set by hand. A pause
+/// already lasting at least as long is left alone, and `None` is returned.
+pub fn start_override(grace: Duration) -> Result<Option<Pause>> {
+    let until = now() + grace.as_secs().max(1);
+    if active().is_some_and(|pause| pause.until.is_none_or(|end| end >= until)) {
+        return Ok(None);
+    }
+
+    let pause = Pause {
+        until: Some(until),
+        manual_override: true,
+    };
+    write(&pause)?;
+    Ok(Some(pause))
+}
+
+fn write(pause: &Pause) -> Result<()> {
+    let path = path().context("HOME not set, cannot pause")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    std::fs::write(&path, serde_json::to_string(pause)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Removes the marker. Returns whether automation was paused.
+pub fn resume() -> Result<bool> {
+    let path = path().context("HOME not set, cannot resume")?;
+    let was_paused = active().is_some();
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(was_paused),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
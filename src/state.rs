@@ -0,0 +1,160 @@
+//! The last light state commanded by the monitor, persisted so a restarted
+//! monitor can tell whether the light was left on, and the outcome of the
+//! latest light command, so `status` can show whether switching works.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::CameraState;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct State {
+    pub light: CameraState,
+    /// Seconds since the Unix epoch.
+    pub updated_at: u64,
+}
+
+impl State {
+    /// Time elapsed since the state was saved.
+    pub fn age(&self) -> Duration {
+        let saved = UNIX_EPOCH + Duration::from_secs(self.updated_at);
+        SystemTime::now()
+            .duration_since(saved)
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+/// The outcome of the latest light command. Kept in its own file, so a
+/// failure doesn't overwrite the state the light was last set to.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LastCommand {
+    /// `on`, `off` or `standby`.
+    pub action: String,
+    /// The lights that didn't switch. Empty when every light did.
+    pub failed: Vec<String>,
+    /// Seconds since the Unix epoch.
+    pub at: u64,
+}
+
+pub fn path() -> Option<PathBuf> {
+    crate::home_dir().map(|home| home.join(".config/elgato-autolight/state.json"))
+}
+
+fn last_command_path() -> Option<PathBuf> {
+    crate::home_dir().map(|home| home.join(".config/elgato-autolight/last_command.json"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, contents)
+        .with_context(|| format!("Failed to write state to {}", path.display()))
+}
+
+/// Reads the saved state. A missing or unreadable file means no state.
+pub fn load() -> Option<State> {
+    load_at(&path()?)
+}
+
+/// Like `load`, but reads `path`.
+fn load_at(path: &Path) -> Option<State> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+pub fn save(light: CameraState) -> Result<()> {
+    save_at(&path().context("HOME not set, cannot save state")?, light)
+}
+
+/// Like `save`, but writes `path`.
+fn save_at(path: &Path, light: CameraState) -> Result<()> {
+    let contents = serde_json::to_string(&State {
+        light,
+        updated_at: now(),
+    })?;
+    write(path, &contents)
+}
+
+pub fn last_command() -> Option<LastCommand> {
+    let contents = std::fs::read_to_string(last_command_path()?).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Does nothing without HOME. The result would only be shown by `status`,
+/// which can't find it either, and the light state saved next warns about
+/// HOME when a light switched.
+pub fn record_command(action: &str, failed: Vec<String>) -> Result<()> {
+    let Some(path) = last_command_path() else {
+        return Ok(());
+    };
+    let contents = serde_json::to_string(&LastCommand {
+        action: action.to_string(),
+        failed,
+        at: now(),
+    })?;
+    write(&path, &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("elgato-autolight-state-{}-{name}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn saved_state_is_loaded_back() {
+        let dir = TempDir::new("round-trip");
+        let path = dir.0.join("config/state.json");
+
+        save_at(&path, CameraState::On).unwrap();
+        let state = load_at(&path).unwrap();
+        assert_eq!(state.light, CameraState::On);
+        assert!(state.age() < Duration::from_secs(60));
+
+        save_at(&path, CameraState::Off).unwrap();
+        assert_eq!(load_at(&path).unwrap().light, CameraState::Off);
+    }
+
+    #[test]
+    fn a_missing_file_is_no_state() {
+        let dir = TempDir::new("missing");
+        assert!(load_at(&dir.0.join("state.json")).is_none());
+    }
+
+    #[test]
+    fn garbage_is_no_state() {
+        let dir = TempDir::new("garbage");
+        let path = dir.0.join("state.json");
+        std::fs::create_dir_all(&dir.0).unwrap();
+
+        for contents in ["", "not json", r#"{"light":"dim","updated_at":1}"#, r#"{"light":"on"}"#] {
+            std::fs::write(&path, contents).unwrap();
+            assert!(load_at(&path).is_none(), "{contents:?} loaded");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Brightness and color temperature within the ranges Elgato lights accept.
+//! Out-of-range values are rejected when the config is parsed, with an error
+//! pointing at the offending field, so every value that reaches
+//! `elgato-light` is valid.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A brightness in percent, 0-100.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "i64", into = "u8")]
+pub struct Brightness(u8);
+
+impl Brightness {
+    pub const MIN: Self = Self(0);
+    pub const MAX: Self = Self(100);
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+
+    /// This brightness scaled by `step / steps`, but never below 1%.
+    pub fn scaled(self, step: u32, steps: u32) -> Self {
+        let level = u32::from(self.0) * step / steps.max(1);
+        Self(level.clamp(1, u32::from(self.0).max(1)) as u8)
+    }
+}
+
+impl TryFrom<i64> for Brightness {
+    type Error = String;
+
+    fn try_from(value: i64) -> Result<Self, String> {
+        if (i64::from(Self::MIN.0)..=i64::from(Self::MAX.0)).contains(&value) {
+            Ok(Self(value as u8))
+        } else {
+            Err(format!("brightness {value} is out of range ({}-{})", Self::MIN, Self::MAX))
+        }
+    }
+}
+
+impl From<Brightness> for u8 {
+    fn from(brightness: Brightness) -> u8 {
+        brightness.0
+    }
+}
+
+impl FromStr for Brightness {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let value: i64 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("brightness '{value}' is not a number"))?;
+        Self::try_from(value)
+    }
+}
+
+impl fmt::Display for Brightness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A color temperature in kelvin, 2900-7000K.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "i64", into = "u16")]
+pub struct Temperature(u16);
+
+impl Temperature {
+    pub const MIN: Self = Self(2900);
+    pub const MAX: Self = Self(7000);
+    /// Fades move in multiples of this, so they don't send changes too small
+    /// to see.
+    const STEP: i64 = 50;
+
+    /// The temperature in the mireds (1,000,000 / kelvin) the Elgato HTTP
+    /// API takes, within the 143-344 it accepts.
+    pub fn mireds(self) -> u16 {
+        let mireds = (1_000_000 + u32::from(self.0) / 2) / u32::from(self.0);
+        mireds.clamp(143, 344) as u16
+    }
+
+    /// The temperature `step / steps` of the way from this one to `target`,
+    /// rounded to a multiple of 50K.
+    pub fn towards(self, target: Self, step: u32, steps: u32) -> Self {
+        let (from, to) = (i64::from(self.0), i64::from(target.0));
+        let value = from + (to - from) * i64::from(step) / i64::from(steps.max(1));
+        let rounded = (value + Self::STEP / 2) / Self::STEP * Self::STEP;
+        Self(rounded.clamp(i64::from(Self::MIN.0), i64::from(Self::MAX.0)) as u16)
+    }
+}
+
+impl TryFrom<i64> for Temperature {
+    type Error = String;
+
+    fn try_from(value: i64) -> Result<Self, String> {
+        if (i64::from(Self::MIN.0)..=i64::from(Self::MAX.0)).contains(&value) {
+            Ok(Self(value as u16))
+        } else {
+            Err(format!("temperature {value}K is out of range ({}-{}K)", Self::MIN, Self::MAX))
+        }
+    }
+}
+
+impl From<Temperature> for u16 {
+    fn from(temperature: Temperature) -> u16 {
+        temperature.0
+    }
+}
+
+impl FromStr for Temperature {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let value: i64 = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("temperature '{value}' is not a number"))?;
+        Self::try_from(value)
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mireds_cover_the_temperature_range() {
+        assert_eq!(Temperature::MIN.mireds(), 344);
+        assert_eq!(Temperature::MAX.mireds(), 143);
+        assert_eq!(Temperature(5000).mireds(), 200);
+    }
+
+    #[test]
+    fn mireds_are_clamped_to_what_the_api_accepts() {
+        assert_eq!(Temperature(2000).mireds(), 344);
+        assert_eq!(Temperature(10000).mireds(), 143);
+    }
+}